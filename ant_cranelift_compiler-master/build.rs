@@ -1,42 +1,117 @@
-use std::fs;
-use std::path::Path;
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 fn main() {
-    // 获取构建配置：debug 或 release
-    let profile = env::var("PROFILE").unwrap(); // debug / release
-    let target_dir = env::var("CARGO_TARGET_DIR").unwrap_or_else(|_| "target".into());
+    // 监控运行时源码变化, 重新执行 build.rs
+    println!("cargo:rerun-if-changed=runtime");
 
-    let target_include_dir = Path::new(&target_dir)
-        .join(&profile)
-        .join("include");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let sources = runtime_sources(Path::new("runtime"));
 
-    let project_include_dir = Path::new("include");
+    // 为 antc 自身的目标平台编译运行时, 产物嵌入 antc, 本机编译时直接写出并链接
+    let mut build = cc::Build::new();
+    build.files(&sources).cargo_metadata(false);
+    build.compile("ant_runtime");
 
-    // 删除已存在的目标目录（保证干净）
-    if target_include_dir.exists() {
-        fs::remove_dir_all(&target_include_dir).unwrap();
-    }
+    // 同一份运行时也链接进 antc, 供 JIT 编译的代码调用
+    println!("cargo:rustc-link-search=native={}", out_dir.display());
+    println!("cargo:rustc-link-lib=static=ant_runtime");
+
+    // MSVC 下产物为 ant_runtime.lib, 其余平台为 libant_runtime.a
+    let lib = ["libant_runtime.a", "ant_runtime.lib"]
+        .into_iter()
+        .map(|it| out_dir.join(it))
+        .find(|it| it.exists())
+        .expect("runtime library not found after compiling");
+
+    println!("cargo:rustc-env=ANT_RUNTIME_LIB={}", lib.display());
+
+    // 缓存键由 DefaultHasher 计算, 换用其他版本的 rustc 构建 antc 后结果可能不同
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .map(|it| String::from_utf8_lossy(&it.stdout).trim().to_string())
+        .unwrap_or_default();
+
+    println!("cargo:rustc-env=ANTC_RUSTC_VERSION={rustc_version}");
+
+    // 交叉编译时需要为目标平台重新编译, 源码同样嵌入 antc
+    let entries = sources
+        .iter()
+        .map(|path| {
+            let path = fs::canonicalize(path).unwrap();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+
+            format!("    ({name:?}, include_str!({:?})),\n", path.display().to_string())
+        })
+        .collect::<String>();
+
+    fs::write(
+        out_dir.join("runtime_sources.rs"),
+        format!("&[\n{entries}]\n"),
+    )
+    .unwrap();
 
-    // 递归复制整个 include 文件夹
-    copy_dir_all(project_include_dir, &target_include_dir).unwrap();
+    // JIT 注册的运行时函数: 取地址让链接器保留它们, 运行时新增的函数无需手动登记
+    let functions = runtime_functions(&sources);
 
-    // 监控 include 变化，重新执行 build.rs
-    println!("cargo:rerun-if-changed=include");
+    let declarations = functions
+        .iter()
+        .map(|name| format!("    fn {name}();\n"))
+        .collect::<String>();
+
+    let symbols = functions
+        .iter()
+        .map(|name| format!("        ({name:?}, {name} as *const u8),\n"))
+        .collect::<String>();
+
+    fs::write(
+        out_dir.join("runtime_symbols.rs"),
+        format!(
+            "unsafe extern \"C\" {{\n{declarations}}}\n\n\
+             pub fn jit_symbols() -> Vec<(&'static str, *const u8)> {{\n    vec![\n{symbols}    ]\n}}\n"
+        ),
+    )
+    .unwrap();
+}
+
+// runtime 目录下的所有 C 文件, 按文件名排序保证产物稳定
+fn runtime_sources(dir: &Path) -> Vec<PathBuf> {
+    let mut sources = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "c"))
+        .collect::<Vec<_>>();
+
+    sources.sort();
+    sources
 }
 
-// 递归复制目录和文件
-fn copy_dir_all(src: &Path, dst: &Path) -> std::io::Result<()> {
-    fs::create_dir_all(dst)?;
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let path = entry.path();
-        let dest_path = dst.join(entry.file_name());
-        if path.is_dir() {
-            copy_dir_all(&path, &dest_path)?;
-        } else {
-            fs::copy(&path, &dest_path)?;
+// 运行时导出的函数: 在行首定义、以 `__` 开头
+fn runtime_functions(sources: &[PathBuf]) -> Vec<String> {
+    let mut functions = vec![];
+
+    for path in sources {
+        for line in fs::read_to_string(path).unwrap().lines() {
+            let definition = line.starts_with(|c: char| c.is_ascii_alphabetic())
+                && !line.starts_with("static")
+                && line.trim_end().ends_with('{');
+
+            let name = line
+                .split_once('(')
+                .filter(|_| definition)
+                .and_then(|(head, _)| {
+                    head.rsplit(|c: char| !c.is_alphanumeric() && c != '_')
+                        .next()
+                })
+                .filter(|it| it.starts_with("__"));
+
+            functions.extend(name.map(str::to_string));
         }
     }
-    Ok(())
+
+    functions
 }
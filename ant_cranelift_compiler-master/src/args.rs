@@ -1,35 +1,202 @@
-use std::fmt::Display;
+use std::{fmt::Display, num::NonZeroUsize, path::PathBuf};
 
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+
+use crate::{
+    i18n::{Lang, set_lang},
+    session::{CrateType, OptLevel, TargetCpu},
+    tr,
+};
 
 #[derive(Parser, Debug, Clone)]
 #[command(
     name = "TypedAntCompiler",
     version = "0.1.0",
     about = "TypedAnt Compiler",
-    long_about = None
+    long_about = None,
+    subcommand_negates_reqs = true
 )]
 
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// 输入文件路径
-    #[arg(short, long)]
-    pub file: String,
+    #[arg(short, long, required_unless_present = "explain")]
+    pub file: Option<String>,
 
     /// 输出路径
     #[arg(short, long)]
     pub output: Option<String>,
 
     /// 优化级别 (0-3, s, z)
-    #[arg(short = 'O', default_value = "0")]
+    #[arg(short = 'O', default_value = "0", global = true)]
     pub opt_level: OptLevelArg, 
 
-    /// 欲链接的静态库文件
-    #[arg(short = 'l', long = "link")]
+    /// 直接交给链接器的文件 (.o, .a, .so)
+    #[arg(long = "link", value_name = "FILE")]
     pub link_with: Vec<String>,
 
-    /// 脚本模式开关
+    /// 库搜索路径
+    #[arg(short = 'L', value_name = "DIR")]
+    pub lib_paths: Vec<String>,
+
+    /// 按名字链接的库 (如 `-l m`)
+    #[arg(short = 'l', value_name = "NAME")]
+    pub libs: Vec<String>,
+
+    /// 原样传给链接器的参数
+    #[arg(long = "link-arg", value_name = "ARG", allow_hyphen_values = true)]
+    pub link_args: Vec<String>,
+
+    /// 链接器 (默认为目标平台的 C 编译器)
+    #[arg(long, value_name = "PATH")]
+    pub linker: Option<String>,
+
+    /// 静态链接 (Windows 的默认方式)
+    #[arg(long = "static", conflicts_with = "dynamic")]
+    pub static_link: bool,
+
+    /// 动态链接 (Windows 以外的默认方式)
+    #[arg(long)]
+    pub dynamic: bool,
+
+    /// 生成位置无关可执行文件 (Windows 以外的默认方式)
+    #[arg(long, conflicts_with = "no_pie")]
+    pub pie: bool,
+
+    /// 生成位置相关的可执行文件
     #[arg(long)]
+    pub no_pie: bool,
+
+    /// 脚本模式开关
+    #[arg(long, global = true)]
     pub script_mode: bool,
+
+    /// 诊断信息输出格式 (human: 带源码片段, json: 每行一个 JSON 对象)
+    #[arg(long, value_enum, default_value_t = ErrorFormat::Human, global = true)]
+    pub error_format: ErrorFormat,
+
+    /// 输出错误码的详细说明 (如 E0001)
+    #[arg(long, value_name = "CODE")]
+    pub explain: Option<String>,
+
+    /// 输出类型 (obj, clif, asm, exe, typed-ast)
+    #[arg(long, value_enum, default_value_t = Emit::Exe)]
+    pub emit: Emit,
+
+    /// 编译产物的种类 (bin, staticlib, cdylib)
+    #[arg(long, value_enum, default_value_t = CrateType::Bin)]
+    pub crate_type: CrateType,
+
+    /// 保留编译过程中的中间文件 (如 `.o`)
+    #[arg(long)]
+    pub save_temps: bool,
+
+    /// 增量编译缓存目录, 未变化的函数直接使用缓存的机器码
+    #[arg(long, value_name = "DIR")]
+    pub incremental: Option<PathBuf>,
+
+    /// 目标平台 (如 aarch64-unknown-linux-gnu), 默认为本机
+    #[arg(long, value_name = "TRIPLE")]
+    pub target: Option<String>,
+
+    /// 生成代码可以使用的 CPU 特性 (native, x86-64-v2, x86-64-v3)
+    #[arg(long, value_enum, global = true)]
+    pub target_cpu: Option<TargetCpu>,
+
+    /// 并行编译函数的线程数, 默认为可用的 CPU 数
+    #[arg(short = 'j', long, value_name = "N", global = true)]
+    pub jobs: Option<NonZeroUsize>,
+
+    /// 直接传给 Cranelift 的设置, 如 `-C preserve_frame_pointers=true`
+    #[arg(short = 'C', value_name = "KEY=VALUE", value_parser = parse_codegen_flag, global = true)]
+    pub codegen: Vec<(String, String)>,
+
+    /// 编译器消息的语言 (默认读取 LANG 环境变量)
+    #[arg(long, value_enum, global = true)]
+    pub lang: Option<Lang>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// 以 JIT 方式在进程内编译程序并直接运行 `main`; 不指定文件时构建 `Ant.toml` 描述的项目并运行
+    Run {
+        /// 输入文件路径
+        file: Option<String>,
+
+        /// 使用 release profile
+        #[arg(long)]
+        release: bool,
+
+        /// `Ant.toml` 的路径, 默认从当前目录向上查找
+        #[arg(long, value_name = "PATH")]
+        manifest_path: Option<String>,
+
+        /// 传给程序的参数
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+
+    /// 按 `Ant.toml` 构建项目, 输出到 `target/<profile>/<name>`
+    Build {
+        /// 使用 release profile
+        #[arg(long)]
+        release: bool,
+
+        /// `Ant.toml` 的路径, 默认从当前目录向上查找
+        #[arg(long, value_name = "PATH")]
+        manifest_path: Option<String>,
+    },
+
+    /// 交互式 REPL, 逐条编译并执行输入的语句与表达式
+    Repl,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    /// 目标文件
+    Obj,
+    /// 每个函数的 Cranelift IR
+    Clif,
+    /// 每个函数最终机器码的反汇编
+    Asm,
+    /// 可执行文件
+    Exe,
+    /// 经过类型检查与单态化的 AST
+    TypedAst,
+}
+
+impl Emit {
+    /// 未指定 `-o` 时输出文件的后缀
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Emit::Obj => "o",
+            Emit::Clif => "clif",
+            Emit::Asm => "s",
+            Emit::TypedAst => "typed-ast",
+            Emit::Exe => {
+                if cfg!(windows) {
+                    "exe"
+                } else {
+                    ""
+                }
+            }
+        }
+    }
+}
+
+pub fn parse_codegen_flag(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(tr!("invalid-codegen-option", option = s)),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -41,7 +208,7 @@ impl std::str::FromStr for OptLevelArg {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "0" | "1" | "2" | "3" | "s" | "z" => Ok(OptLevelArg(s.to_string())),
-            _ => Err(format!("无效的优化级别: {}. 可选值: 0, 1, 2, 3, s, z", s)),
+            _ => Err(tr!("invalid-opt-level", level = s)),
         }
     }
 }
@@ -54,6 +221,17 @@ impl OptLevelArg {
     pub fn is_optimized(&self) -> bool {
         self.0 != "0"
     }
+
+    pub fn level(&self) -> OptLevel {
+        match self.0.as_str() {
+            "1" => OptLevel::O1,
+            "2" => OptLevel::O2,
+            "3" => OptLevel::O3,
+            "s" => OptLevel::Size,
+            "z" => OptLevel::MinSize,
+            _ => OptLevel::O0,
+        }
+    }
 }
 
 impl Display for OptLevelArg {
@@ -62,10 +240,75 @@ impl Display for OptLevelArg {
     }
 }
 
-pub static mut ARG: Option<Args> = {
+
+/// 在 clap 解析之前找出 `--lang`, 使帮助信息和参数错误也使用对应语言
+fn pre_scan_lang() -> Option<Lang> {
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--lang") {
+            Some("") => args.next()?,
+            Some(rest) => rest.strip_prefix('=')?.to_string(),
+            None => continue,
+        };
+
+        return <Lang as ValueEnum>::from_str(&value, true).ok();
+    }
+
     None
-};
+}
 
-pub fn read_arg() -> Option<Args> {
-    unsafe { (*&raw const ARG).clone() }
-}
\ No newline at end of file
+/// 解析命令行参数, 帮助信息按当前语言输出
+pub fn parse_args() -> Args {
+    if let Some(lang) = pre_scan_lang() {
+        set_lang(lang);
+    }
+
+    let command = Args::command()
+        .about(tr!("cli-about"))
+        .mut_arg("file", |it| it.help(tr!("cli-file")))
+        .mut_arg("output", |it| it.help(tr!("cli-output")))
+        .mut_arg("opt_level", |it| it.help(tr!("cli-opt-level")))
+        .mut_arg("link_with", |it| it.help(tr!("cli-link")))
+        .mut_arg("lib_paths", |it| it.help(tr!("cli-lib-path")))
+        .mut_arg("libs", |it| it.help(tr!("cli-lib")))
+        .mut_arg("link_args", |it| it.help(tr!("cli-link-arg")))
+        .mut_arg("linker", |it| it.help(tr!("cli-linker")))
+        .mut_arg("static_link", |it| it.help(tr!("cli-static")))
+        .mut_arg("dynamic", |it| it.help(tr!("cli-dynamic")))
+        .mut_arg("pie", |it| it.help(tr!("cli-pie")))
+        .mut_arg("no_pie", |it| it.help(tr!("cli-no-pie")))
+        .mut_arg("script_mode", |it| it.help(tr!("cli-script-mode")))
+        .mut_arg("error_format", |it| it.help(tr!("cli-error-format")))
+        .mut_arg("explain", |it| it.help(tr!("cli-explain")))
+        .mut_arg("emit", |it| it.help(tr!("cli-emit")))
+        .mut_arg("crate_type", |it| it.help(tr!("cli-crate-type")))
+        .mut_arg("save_temps", |it| it.help(tr!("cli-save-temps")))
+        .mut_arg("incremental", |it| it.help(tr!("cli-incremental")))
+        .mut_arg("target", |it| it.help(tr!("cli-target")))
+        .mut_arg("target_cpu", |it| it.help(tr!("cli-target-cpu")))
+        .mut_arg("jobs", |it| it.help(tr!("cli-jobs")))
+        .mut_arg("codegen", |it| it.help(tr!("cli-codegen")))
+        .mut_arg("lang", |it| it.help(tr!("cli-lang")))
+        .mut_subcommand("run", |it| {
+            it.about(tr!("cli-run"))
+                .mut_arg("file", |it| it.help(tr!("cli-file")))
+                .mut_arg("release", |it| it.help(tr!("cli-release")))
+                .mut_arg("manifest_path", |it| it.help(tr!("cli-manifest-path")))
+                .mut_arg("args", |it| it.help(tr!("cli-run-args")))
+        })
+        .mut_subcommand("build", |it| {
+            it.about(tr!("cli-build"))
+                .mut_arg("release", |it| it.help(tr!("cli-release")))
+                .mut_arg("manifest_path", |it| it.help(tr!("cli-manifest-path")))
+        })
+        .mut_subcommand("repl", |it| it.about(tr!("cli-repl")));
+
+    let args = Args::from_arg_matches(&command.get_matches()).unwrap_or_else(|it| it.exit());
+
+    if let Some(lang) = args.lang {
+        set_lang(lang);
+    }
+
+    args
+}
@@ -0,0 +1,347 @@
+//! 增量编译缓存: 以函数内容的哈希为键, 保存函数的机器码与重定位
+//!
+//! 键包括单态化后的函数、它能看到的全局符号与函数声明, 以及目标平台与 Cranelift 设置,
+//! 都没有变化时直接定义缓存的机器码, 不再运行 Cranelift
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+};
+
+use ant_type_checker::{
+    ty::Ty,
+    typed_ast::{GetType, typed_expr::TypedExpression, typed_stmt::TypedStatement},
+};
+use cranelift_codegen::{
+    CompiledCode, FinalizedMachReloc, FinalizedRelocTarget,
+    binemit::{Addend, CodeOffset, Reloc},
+    ir::{ExternalName, Function, KnownSymbol, LibCall, UserExternalName},
+    isa::TargetIsa,
+};
+use cranelift_module::{DataId, FuncId, FuncOrDataId, Module};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compiler::{Compiler, GlobalState, isa_fingerprint, table::mangle},
+    diagnostic::Diagnostic,
+    module::separate::hash,
+    optimizer::walk_stmt,
+    session::CompileOptions,
+    tr,
+};
+
+pub struct FunctionCache {
+    dir: PathBuf,
+    /// 编译器版本、构建编译器的 rustc 版本、优化级别与目标平台, 不同时缓存的机器码不能使用
+    fingerprint: String,
+    /// 本次编译中使用了缓存的函数
+    pub hits: Vec<String>,
+}
+
+/// 缓存的一个函数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedFunction {
+    pub alignment: u64,
+    pub code: Vec<u8>,
+    pub relocs: Vec<CachedReloc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedReloc {
+    pub offset: CodeOffset,
+    pub kind: Reloc,
+    pub addend: Addend,
+    pub target: CachedTarget,
+}
+
+/// 重定位的目标按名字记录, 函数与数据对象的 id 每次编译都可能不同
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CachedTarget {
+    Function(String),
+    /// 常量等具名的数据对象
+    Data(String),
+    /// 字符串字面量按内容记录 (含结尾的 `\0`), 它的数据对象名与编译顺序有关
+    Str(String),
+    LibCall(LibCall),
+    KnownSymbol(KnownSymbol),
+    /// 函数自身中的偏移
+    Offset(CodeOffset),
+}
+
+impl FunctionCache {
+    pub fn new(dir: PathBuf, options: &CompileOptions, target_isa: &dyn TargetIsa) -> Self {
+        Self {
+            dir,
+            fingerprint: format!(
+                "{}\n{}\n{:?}\n{}",
+                env!("CARGO_PKG_VERSION"),
+                env!("ANTC_RUSTC_VERSION"),
+                options.opt_level,
+                isa_fingerprint(target_isa)
+            ),
+            hits: vec![],
+        }
+    }
+
+    /// 顶层函数的缓存键, 需要在函数声明之后、编译之前计算
+    ///
+    /// 只包含函数引用到的声明与符号, 新增或修改无关的顶层定义时仍能命中
+    pub fn key<M: Module>(&self, function: &TypedStatement, state: &GlobalState<'_, M>) -> u64 {
+        let declarations = state.module.declarations();
+
+        // 无法遍历函数体时退回到包含所有声明与符号
+        let names = referenced_names(function);
+        let referenced = |name: &str| names.as_ref().is_none_or(|it| it.contains(name));
+
+        // 调用的函数按名字与签名生成代码
+        let mut functions = state
+            .function_map
+            .iter()
+            .filter(|(name, _)| referenced(name))
+            .map(|(name, id)| {
+                let decl = declarations.get_function_decl(*id);
+                format!("{name} {:?} {:?}", decl.name, decl.signature)
+            })
+            .collect::<Vec<_>>();
+
+        // 全局符号决定标识符如何读取, 其中包括结构体布局
+        let mut symbols = state
+            .table
+            .borrow()
+            .map
+            .values()
+            .filter(|it| referenced(&it.name))
+            .map(|it| format!("{} {:?} {:?} {}", it.name, it.scope, it.symbol_ty, it.is_val))
+            .collect::<Vec<_>>();
+
+        let mut data = state
+            .data_map
+            .keys()
+            .filter(|it| !it.ends_with('\0') && referenced(it))
+            .collect::<Vec<_>>();
+
+        // 各个表无序, 排序后键才稳定
+        functions.sort();
+        symbols.sort();
+        data.sort();
+
+        hash(&format!(
+            "{}\n{}\n{functions:?}\n{symbols:?}\n{data:?}",
+            self.fingerprint,
+            strip_positions(&format!("{function:?}")),
+        ))
+    }
+
+    /// 读取失败视为未命中
+    pub fn load(&self, key: u64) -> Option<CachedFunction> {
+        serde_json::from_str(&fs::read_to_string(self.path(key)).ok()?).ok()
+    }
+
+    /// 写入失败只是下次无法命中, 不影响本次编译
+    pub fn store(&self, key: u64, function: &CachedFunction) {
+        if fs::create_dir_all(&self.dir).is_ok() {
+            let _ = fs::write(self.path(key), serde_json::to_string(function).unwrap());
+        }
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{key:016x}.json"))
+    }
+}
+
+impl CachedFunction {
+    /// 由编译结果得到缓存项; 引用了无法按名字记录的目标时为 None
+    pub fn new<M: Module>(
+        module: &M,
+        data_map: &HashMap<String, DataId>,
+        func: &Function,
+        compiled: &CompiledCode,
+    ) -> Option<Self> {
+        let declarations = module.declarations();
+
+        let relocs = compiled
+            .buffer
+            .relocs()
+            .iter()
+            .map(|reloc| {
+                let target = match &reloc.target {
+                    FinalizedRelocTarget::Func(offset) => CachedTarget::Offset(*offset),
+                    FinalizedRelocTarget::ExternalName(ExternalName::LibCall(it)) => {
+                        CachedTarget::LibCall(*it)
+                    }
+                    FinalizedRelocTarget::ExternalName(ExternalName::KnownSymbol(it)) => {
+                        CachedTarget::KnownSymbol(*it)
+                    }
+                    // cranelift_module 中命名空间 0 为函数, 1 为数据对象
+                    FinalizedRelocTarget::ExternalName(ExternalName::User(name)) => {
+                        let name = &func.params.user_named_funcs()[*name];
+
+                        match name.namespace {
+                            0 => CachedTarget::Function(
+                                declarations
+                                    .get_function_decl(FuncId::from_u32(name.index))
+                                    .name
+                                    .clone()?,
+                            ),
+                            1 => {
+                                let id = DataId::from_u32(name.index);
+
+                                match data_map
+                                    .iter()
+                                    .find(|(content, it)| **it == id && content.ends_with('\0'))
+                                {
+                                    Some((content, _)) => CachedTarget::Str(content.clone()),
+                                    None => CachedTarget::Data(
+                                        declarations.get_data_decl(id).name.clone()?,
+                                    ),
+                                }
+                            }
+                            _ => None?,
+                        }
+                    }
+                    _ => None?,
+                };
+
+                Some(CachedReloc {
+                    offset: reloc.offset,
+                    kind: reloc.kind,
+                    addend: reloc.addend,
+                    target,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self {
+            alignment: compiled.buffer.alignment as u64,
+            code: compiled.code_buffer().to_vec(),
+            relocs,
+        })
+    }
+
+    /// 用缓存的机器码定义函数, `func` 只需带有签名
+    ///
+    /// 引用的函数或数据对象在本次编译中不存在时返回 false, 由调用方照常编译
+    pub fn define<M: Module>(
+        &self,
+        state: &mut GlobalState<'_, M>,
+        name: &str,
+        func_id: FuncId,
+        func: &mut Function,
+    ) -> Result<bool, Diagnostic> {
+        let mut relocs = Vec::with_capacity(self.relocs.len());
+
+        for reloc in &self.relocs {
+            let user = |func: &mut Function, namespace, index| {
+                let name = func.declare_imported_user_function(UserExternalName::new(namespace, index));
+                FinalizedRelocTarget::ExternalName(ExternalName::User(name))
+            };
+
+            let target = match &reloc.target {
+                CachedTarget::Function(name) => match state.module.get_name(name) {
+                    Some(FuncOrDataId::Func(id)) => user(func, 0, id.as_u32()),
+                    _ => return Ok(false),
+                },
+                CachedTarget::Data(name) => match state.module.get_name(name) {
+                    Some(FuncOrDataId::Data(id)) => user(func, 1, id.as_u32()),
+                    _ => return Ok(false),
+                },
+                CachedTarget::Str(content) => {
+                    let id = Compiler::<M>::string_data(
+                        state.module,
+                        state.data_map,
+                        state.str_counter,
+                        content.clone(),
+                    )?;
+
+                    user(func, 1, id.as_u32())
+                }
+                CachedTarget::LibCall(it) => {
+                    FinalizedRelocTarget::ExternalName(ExternalName::LibCall(*it))
+                }
+                CachedTarget::KnownSymbol(it) => {
+                    FinalizedRelocTarget::ExternalName(ExternalName::KnownSymbol(*it))
+                }
+                CachedTarget::Offset(it) => FinalizedRelocTarget::Func(*it),
+            };
+
+            relocs.push(FinalizedMachReloc {
+                offset: reloc.offset,
+                kind: reloc.kind,
+                addend: reloc.addend,
+                target,
+            });
+        }
+
+        state
+            .module
+            .define_function_bytes(func_id, func, self.alignment, &self.code, &relocs)
+            .map_err(|e| tr!("define-function-failed", name = name, error = e))?;
+
+        Ok(true)
+    }
+}
+
+/// 函数体中的标识符、用到的结构体类型与调用的方法 (`类型::方法`)
+///
+/// 遇到无法遍历的语法结构时为 None
+fn referenced_names(function: &TypedStatement) -> Option<HashSet<String>> {
+    let mut names = HashSet::new();
+
+    let complete = walk_stmt(&mut function.clone(), &mut |expr| {
+        match expr {
+            TypedExpression::Ident(ident, _) => {
+                names.insert(ident.value.to_string());
+            }
+            TypedExpression::FieldAccess(obj, field, _) => {
+                if let Ty::Struct { name, .. } = obj.get_type() {
+                    names.insert(mangle(&name, &field.value));
+                }
+            }
+            _ => {}
+        }
+
+        if let Ty::Struct { name, .. } = expr.get_type() {
+            names.insert(name.to_string());
+        }
+    });
+
+    complete.then_some(names)
+}
+
+/// 去掉 Debug 输出中 Token 的行号与列号, 函数只是在文件中移动了位置时仍能命中
+///
+/// 字符串中的内容原样保留
+fn strip_positions(debug: &str) -> String {
+    let mut result = String::with_capacity(debug.len());
+    let mut chars = debug.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        result.push(c);
+
+        match c {
+            '\\' if in_string => result.extend(chars.next()),
+            '"' => in_string = !in_string,
+            ' ' if !in_string && (result.ends_with("line: ") || result.ends_with("column: ")) => {
+                while chars.next_if(char::is_ascii_digit).is_some() {}
+            }
+            _ => {}
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::cache::strip_positions;
+
+    #[test]
+    fn strip_token_positions() {
+        assert_eq!(
+            strip_positions(r#"Token { value: "line: 1\"", line: 12, column: 3 }"#),
+            r#"Token { value: "line: 1\"", line: , column:  }"#
+        );
+    }
+}
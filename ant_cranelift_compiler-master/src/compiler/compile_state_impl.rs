@@ -1,9 +1,15 @@
 use std::{cell::RefCell, rc::Rc};
 
-use crate::compiler::{FunctionState, table::SymbolTable};
+use cranelift::prelude::{InstBuilder, TrapCode, Value, types};
+use cranelift_module::Module;
+
+use crate::{
+    compiler::{FunctionState, table::SymbolTable},
+    diagnostic::Diagnostic,
+};
 
 #[allow(unused)]
-impl<'a> FunctionState<'a> {
+impl<'a, M: Module> FunctionState<'a, M> {
     pub fn enter_scope(&mut self) {
         let outer = self.table.clone();
 
@@ -23,4 +29,21 @@ impl<'a> FunctionState<'a> {
 
         Some(before_leave_table)
     }
-}
\ No newline at end of file
+
+    /// 记录错误并插入 trap 占位, 随后切换到一个新的块继续编译后续语句
+    ///
+    /// 出现错误的函数不会被定义到模块中, 这里只需保证 builder 仍可继续使用
+    pub fn recover(&mut self, diagnostic: Diagnostic) -> Value {
+        self.diagnostics.push(diagnostic);
+
+        if !self.builder.is_filled() {
+            self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+        }
+
+        let block = self.builder.create_block();
+        self.builder.switch_to_block(block);
+        self.builder.seal_block(block);
+
+        self.builder.ins().iconst(types::I64, 0)
+    }
+}
@@ -2,16 +2,17 @@ use std::{
     cell::RefCell,
     collections::HashMap,
     rc::Rc,
-    sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}},
+    sync::{Arc, Mutex},
 };
 
 use cranelift::prelude::{AbiParam, InstBuilder, MemFlags, Signature, Value, types};
 use cranelift_codegen::{
+    CompiledCode,
     ir::{FuncRef, Function, UserFuncName},
     isa::TargetIsa,
 };
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
-use cranelift_module::{Linkage, Module, default_libcall_names};
+use cranelift_module::{DataId, FuncId, Linkage, Module, default_libcall_names};
 use cranelift_object::{ObjectBuilder, ObjectModule};
 
 use ant_ast::node::GetToken;
@@ -26,65 +27,100 @@ use ant_type_checker::{
 };
 
 use crate::{
-    args::read_arg,
+    diagnostic::{Diagnostic, Diagnostics, codes::ErrorCode},
+    tr,
     compiler::{
-        CompileState, Compiler, FunctionState, GlobalState,
-        constants::CALL_CONV,
+        CodeDump, CompileState, CompiledProgram, Compiler, FunctionDump, FunctionState,
+        GlobalState,
+        cache::{CachedFunction, FunctionCache},
+        parallel::{PendingFunction, compile_all},
         convert_type::convert_type_to_cranelift_type,
-        handler::compile_infix::compile_infix,
-        imm::{int_value_to_imm, platform_width_to_int_type},
-        table::{StructLayout, SymbolScope, SymbolTable, SymbolTy},
+        handler::{compile_infix::compile_infix, compile_prefix::compile_prefix},
+        imm::int_value_to_imm,
+        table::{StructLayout, SymbolScope, SymbolTable, SymbolTy, mangle},
     },
+    optimizer::Optimizer,
+    session::CompileOptions,
     traits::{LiteralExprToConst, NeedGc, ToLeBytes},
 };
 
-pub static STR_COUNTER: AtomicUsize = AtomicUsize::new(1);
-
-impl Compiler {
+impl Compiler<ObjectModule> {
     pub fn new(
         target_isa: Arc<dyn TargetIsa>,
-        file: Arc<str>,
         table: Rc<RefCell<SymbolTable>>,
         type_table: Arc<Mutex<TypeTable>>,
-    ) -> Compiler {
+        options: CompileOptions,
+    ) -> Result<Compiler, Diagnostic> {
         // 创建 ObjectModule
-        let builder =
-            ObjectBuilder::new(target_isa.clone(), file.as_bytes(), default_libcall_names())
-                .expect("Failed to create ObjectBuilder");
+        let builder = ObjectBuilder::new(
+            target_isa.clone(),
+            options.file_name.as_bytes(),
+            default_libcall_names(),
+        )
+        .map_err(|e| tr!("create-object-builder-failed", error = e))?;
+
+        Self::from_module(ObjectModule::new(builder), target_isa, table, type_table, options)
+    }
+
+    /// 编译整个程序, 返回目标文件内容或本次编译中的所有错误
+    pub fn compile_program(mut self, program: TypedNode) -> Result<CompiledProgram, Vec<Diagnostic>> {
+        self.codegen(program)?;
 
-        let mut module = ObjectModule::new(builder);
+        let obj = self.module.finish();
+        let object = obj
+            .emit()
+            .map_err(|it| vec![Diagnostic::error(tr!("emit-object-failed", error = it))])?;
+
+        Ok(CompiledProgram {
+            object,
+            functions: self.dump.functions,
+            cached: self.cache.map(|it| it.hits).unwrap_or_default(),
+        })
+    }
+}
 
-        let ptr_ty = platform_width_to_int_type();
+impl<M: Module> Compiler<M> {
+    /// 在给定的 module 上创建编译器, 并声明 ARC 运行时函数
+    pub fn from_module(
+        mut module: M,
+        target_isa: Arc<dyn TargetIsa>,
+        table: Rc<RefCell<SymbolTable>>,
+        type_table: Arc<Mutex<TypeTable>>,
+        options: CompileOptions,
+    ) -> Result<Self, Diagnostic> {
+        let ptr_ty = target_isa.pointer_type();
+
+        let cache = options
+            .incremental
+            .clone()
+            .map(|dir| FunctionCache::new(dir, &options, &*target_isa));
 
         // void* __obj_alloc(size_t)
-        let mut alloc_sig = Signature::new(CALL_CONV);
+        let mut alloc_sig = Signature::new(target_isa.default_call_conv());
         alloc_sig.params.push(AbiParam::new(ptr_ty));
         alloc_sig.returns.push(AbiParam::new(ptr_ty));
 
         let arc_alloc = module
             .declare_function("__obj_alloc", Linkage::Import, &alloc_sig)
-            .map_err(|e| e.to_string())
-            .expect("cannot declare function '__obj_alloc'");
+            .map_err(|e| tr!("declare-function-failed", name = "__obj_alloc", error = e))?;
 
         // void __obj_retain(void*)
-        let mut retain_sig = Signature::new(CALL_CONV);
+        let mut retain_sig = Signature::new(target_isa.default_call_conv());
         retain_sig.params.push(AbiParam::new(ptr_ty));
 
         let arc_retain = module
             .declare_function("__obj_retain", Linkage::Import, &retain_sig)
-            .map_err(|e| e.to_string())
-            .expect("cannot declare function '__obj_retain'");
+            .map_err(|e| tr!("declare-function-failed", name = "__obj_retain", error = e))?;
 
         // void __obj_release(void*)
-        let mut release_sig = Signature::new(CALL_CONV);
+        let mut release_sig = Signature::new(target_isa.default_call_conv());
         release_sig.params.push(AbiParam::new(ptr_ty));
 
         let arc_release = module
             .declare_function("__obj_release", Linkage::Import, &release_sig)
-            .map_err(|e| e.to_string())
-            .expect("cannot declare function '__obj_release'");
+            .map_err(|e| tr!("declare-function-failed", name = "__obj_release", error = e))?;
 
-        Self {
+        Ok(Self {
             module,
             builder_ctx: FunctionBuilderContext::new(),
             context: cranelift_codegen::Context::new(),
@@ -98,15 +134,157 @@ impl Compiler {
             arc_alloc,
             arc_release,
             arc_retain,
+
+            diagnostics: Diagnostics::new(),
+            dump: CodeDump::default(),
+
+            library: options.crate_type.is_library(),
+            options,
+            str_counter: 0,
+            cache,
+        })
+    }
+
+    /// 生成的函数供其他目标文件调用 (库或分别编译的模块), 保留 `main` 用不到的函数
+    pub fn library(mut self, library: bool) -> Self {
+        self.library = library;
+        self
+    }
+
+    /// 编译时记录每个函数的 Cranelift IR (`clif`) 和最终机器码的反汇编 (`asm`)
+    pub fn dump_functions(mut self, clif: bool, asm: bool) -> Self {
+        self.dump.clif = clif;
+        self.dump.asm = asm;
+        self
+    }
+
+    /// 编译并定义一个函数, 需要时保存它的 IR 与反汇编
+    pub(super) fn define_function(
+        module: &mut M,
+        dump: &mut CodeDump,
+        name: &str,
+        func_id: FuncId,
+        ctx: &mut cranelift_codegen::Context,
+    ) -> Result<(), Diagnostic> {
+        let clif = dump.clif.then(|| ctx.func.display().to_string());
+
+        ctx.set_disasm(dump.asm);
+
+        module
+            .define_function(func_id, ctx)
+            .map_err(|e| tr!("define-function-failed", name = name, error = e))?;
+
+        if dump.is_enabled() {
+            let asm = ctx.compiled_code().and_then(|it| it.vcode.clone());
+
+            dump.functions.push(FunctionDump {
+                name: name.to_string(),
+                clif,
+                asm,
+            });
+        }
+
+        module.clear_context(ctx);
+
+        Ok(())
+    }
+
+    /// 定义在线程池中编译好的函数, 需要时保存它的 IR 与反汇编并写入增量编译缓存
+    fn define_compiled(
+        state: &mut GlobalState<'_, M>,
+        function: PendingFunction,
+    ) -> Result<(), Diagnostic> {
+        let name = function.name;
+
+        if let Some(error) = function.error {
+            return Err(tr!("define-function-failed", name = name, error = error).into());
+        }
+
+        let Some(compiled) = function.ctx.compiled_code() else {
+            let error = tr!("function-not-compiled");
+            return Err(tr!("define-function-failed", name = name, error = error).into());
+        };
+
+        state
+            .module
+            .define_function_bytes(
+                function.func_id,
+                &function.ctx.func,
+                compiled.buffer.alignment as u64,
+                compiled.code_buffer(),
+                compiled.buffer.relocs(),
+            )
+            .map_err(|e| tr!("define-function-failed", name = name, error = e))?;
+
+        if state.dump.is_enabled() {
+            state.dump.functions.push(FunctionDump {
+                name,
+                clif: function.clif,
+                asm: compiled.vcode.clone(),
+            });
+        }
+
+        if let Some(key) = function.key {
+            Self::store_in_cache(state, key, &function.ctx.func, compiled);
+        }
+
+        Ok(())
+    }
+
+    /// 将编译结果写入增量编译缓存
+    fn store_in_cache(
+        state: &GlobalState<'_, M>,
+        key: u64,
+        func: &Function,
+        compiled: &CompiledCode,
+    ) {
+        if let Some(cache) = state.cache.as_deref()
+            && let Some(cached) = CachedFunction::new(state.module, state.data_map, func, compiled)
+        {
+            cache.store(key, &cached);
         }
     }
 
+    /// 字符串字面量的数据对象, `content` 含结尾的 `\0`; 相同内容只生成一次
+    pub(super) fn string_data(
+        module: &mut M,
+        data_map: &mut HashMap<String, DataId>,
+        str_counter: &mut usize,
+        content: String,
+    ) -> Result<DataId, Diagnostic> {
+        // 获取当前是本次编译中的第几个字符串 (从一开始计数)
+        *str_counter += 1;
+        let str_count = *str_counter;
+
+        if let Some(id) = data_map.get(&content) {
+            return Ok(*id);
+        }
+
+        let name = format!("str_{}_{str_count}", content.len());
+        let id = module
+            .declare_data(&name, Linkage::Local, true, false)
+            .map_err(|e| tr!("declare-string-failed", error = e))?;
+        let mut desc = cranelift_module::DataDescription::new();
+
+        // 使用 Init::Bytes
+        desc.init = cranelift_module::Init::Bytes {
+            contents: content.clone().into_bytes().into_boxed_slice(),
+        };
+        module
+            .define_data(id, &desc)
+            .map_err(|e| tr!("define-string-failed", error = e))?;
+
+        data_map.insert(content, id);
+
+        Ok(id)
+    }
+
     /// 在编译阶段计算 struct 布局（目标平台相关）
     fn compile_struct_layout(
         state: &impl CompileState,
         name: &Arc<str>,
         fields: &[(Arc<str>, Ty)],
-    ) -> Result<StructLayout, String> {
+    ) -> Result<StructLayout, Diagnostic> {
         let pointer_width = state.get_target_isa().pointer_bytes() as u32;
 
         let mut new_fields: Vec<(Arc<str>, Ty)> = Vec::with_capacity(fields.len() + 1);
@@ -156,24 +334,28 @@ impl Compiler {
         state: &impl CompileState,
         ty: &Ty,
         pointer_width: u32,
-    ) -> Result<u32, String> {
+    ) -> Result<u32, Diagnostic> {
         match ty {
+            Ty::IntTy(IntTy::USize | IntTy::ISize) => Ok(pointer_width),
             Ty::IntTy(it) => Ok(it.get_bytes_size() as u32),
             Ty::Bool => Ok(1),
             Ty::Str => Ok(pointer_width),
             Ty::Struct { name, .. } => {
                 let SymbolTy::Struct(layout) =
                     state.get_table().borrow_mut().get(name).map_or_else(
-                        || Err(format!("undefine struct: {name}")),
+                        || {
+                            Err(Diagnostic::error(tr!("undefined-struct", name = name))
+                                .with_code(ErrorCode::E0005))
+                        },
                         |it| Ok(it.symbol_ty),
                     )?
                 else {
-                    Err(format!("not a struct: {name}"))?
+                    Err(tr!("not-a-struct", name = name))?
                 };
 
                 Ok(layout.size)
             }
-            _ => todo!(),
+            _ => Err(Diagnostic::unsupported(tr!("construct-field-type", ty = ty))),
         }
     }
 
@@ -181,24 +363,28 @@ impl Compiler {
         state: &impl CompileState,
         ty: &Ty,
         pointer_width: u32,
-    ) -> Result<u32, String> {
+    ) -> Result<u32, Diagnostic> {
         match ty {
+            Ty::IntTy(IntTy::USize | IntTy::ISize) => Ok(pointer_width),
             Ty::IntTy(it) => Ok(it.get_bytes_size() as u32),
             Ty::Bool => Ok(1),
             Ty::Str => Ok(pointer_width),
             Ty::Struct { name, .. } => {
                 let SymbolTy::Struct(layout) =
                     state.get_table().borrow_mut().get(name).map_or_else(
-                        || Err(format!("undefine struct: {name}")),
+                        || {
+                            Err(Diagnostic::error(tr!("undefined-struct", name = name))
+                                .with_code(ErrorCode::E0005))
+                        },
                         |it| Ok(it.symbol_ty),
                     )?
                 else {
-                    Err(format!("not a struct: {name}"))?
+                    Err(tr!("not-a-struct", name = name))?
                 };
 
                 Ok(layout.align)
             }
-            _ => todo!(),
+            _ => Err(Diagnostic::unsupported(tr!("construct-field-type", ty = ty))),
         }
     }
 
@@ -210,23 +396,39 @@ impl Compiler {
         )
     }
 
+    /// 非脚本模式下编译的顶层语句: 除函数与常量外, 还有结构体、`extern` 函数与 `impl`
+    pub fn is_item(stmt: &TypedStatement) -> bool {
+        Self::is_top_level_stmt(stmt)
+            || matches!(
+                stmt,
+                TypedStatement::Struct { .. }
+                    | TypedStatement::Extern { .. }
+                    | TypedStatement::Impl { .. }
+            )
+    }
+
     pub fn compile_top_level_stmt(
-        state: &mut GlobalState,
+        state: &mut GlobalState<'_, M>,
         stmt: &TypedStatement,
-    ) -> Result<(), String> {
+    ) -> Result<(), Diagnostic> {
+        let ptr_ty = state.target_isa.pointer_type();
+
         match stmt {
             TypedStatement::Const {
                 name, value, ..
             } => {
                 let const_val = value.to_const().map_or_else(
-                    || Err(format!("expression `{value}` is not a constant")),
+                    || {
+                        Err(Diagnostic::error(tr!("not-a-constant", expr = value))
+                            .with_code(ErrorCode::E0004))
+                    },
                     |it| Ok(it),
                 )?;
 
                 let data_id = state
                     .module
                     .declare_data(&name.value, Linkage::Local, false, false) // Declare as Local
-                    .unwrap();
+                    .map_err(|e| tr!("declare-const-failed", name = name.value, error = e))?;
 
                 let mut data_desc = cranelift_module::DataDescription::new();
                 data_desc.init = cranelift_module::Init::Bytes {
@@ -235,7 +437,10 @@ impl Compiler {
 
                 state.data_map.insert(name.value.to_string(), data_id);
                 
-                state.module.define_data(data_id, &data_desc).unwrap();
+                state
+                    .module
+                    .define_data(data_id, &data_desc)
+                    .map_err(|e| tr!("define-const-failed", name = name.value, error = e))?;
 
                 state.table.borrow_mut().define(&name.value);
 
@@ -247,46 +452,37 @@ impl Compiler {
                 block: block_ast,
                 ..
             }) => {
-                let mut converted_params = vec![];
-
-                for param in params {
-                    converted_params.push(AbiParam::new(convert_type_to_cranelift_type(
-                        &param.get_type(),
-                    )));
-                }
-
                 let mut ctx = state.module.make_context();
-                ctx.func.signature = Signature::new(CALL_CONV);
-                ctx.func.signature.params.append(&mut converted_params);
-
-                if block_ast.get_type() != Ty::Unit {
-                    ctx.func
-                        .signature
-                        .returns
-                        .push(AbiParam::new(convert_type_to_cranelift_type(
-                            &block_ast.get_type(),
-                        )));
-                }
+                ctx.func.signature = Self::function_signature(state, params, block_ast)?;
 
                 if let Some(name) = name.as_ref() {
                     let name = &name.value;
 
-                    // 1. 首先声明函数
-                    let func_id = match state.module.declare_function(
-                        &name,
-                        Linkage::Export,
-                        &ctx.func.signature,
-                    ) {
-                        Ok(it) => it,
-                        Err(it) => Err(it.to_string())?,
-                    };
-
-                    // 2. 立即将函数ID注册到function_map中
-                    state.function_map.insert(name.to_string(), func_id);
+                    // 1. 首先声明函数, 并立即将函数ID注册到function_map中
+                    //    (并行编译时已提前声明过, 再次声明得到同一个 FuncId)
+                    let func_id = Self::declare_function(state, name, &ctx.func.signature)?;
 
                     // 3. 定义外部作用域的符号
                     let _func_symbol = state.table.borrow_mut().define_func(&name);
 
+                    // 函数与它依赖的内容都没有变化时直接使用缓存的机器码
+                    let key = match state.cache.as_deref() {
+                        Some(cache) if !state.dump.is_enabled() => Some(cache.key(stmt, state)),
+                        _ => None,
+                    };
+
+                    if let Some(key) = key
+                        && let Some(cached) = state.cache.as_deref().and_then(|it| it.load(key))
+                        && cached.define(state, name, func_id, &mut ctx.func)?
+                    {
+                        if let Some(cache) = state.cache.as_deref_mut() {
+                            cache.hits.push(name.to_string());
+                        }
+
+                        state.module.clear_context(&mut ctx);
+                        return Ok(());
+                    }
+
                     // 4. 创建新的编译上下文
                     let mut func_builder_ctx = FunctionBuilderContext::new();
                     let mut func_builder =
@@ -305,7 +501,7 @@ impl Compiler {
                     let inner_func_symbol = func_symbol_table.borrow_mut().define_func(&name);
                     func_builder.declare_var(
                         Variable::from_u32(inner_func_symbol.var_index as u32),
-                        platform_width_to_int_type(),
+                        ptr_ty,
                     );
 
                     // 在函数内部重新创建FuncRef和对应的Value
@@ -314,7 +510,7 @@ impl Compiler {
                         .declare_func_in_func(func_id, &mut func_builder.func);
                     let inner_ref_val = func_builder
                         .ins()
-                        .func_addr(platform_width_to_int_type(), inner_func_ref);
+                        .func_addr(ptr_ty, inner_func_ref);
                     func_builder.def_var(
                         Variable::from_u32(inner_func_symbol.var_index as u32),
                         inner_ref_val, // 使用内部创建的Value
@@ -324,7 +520,7 @@ impl Compiler {
                     for (i, param) in params.iter().enumerate() {
                         if let TypedExpression::TypeHint(param_name, _, ty) = &**param {
                             let symbol = func_symbol_table.borrow_mut().define(&param_name.value);
-                            let cranelift_ty = convert_type_to_cranelift_type(ty);
+                            let cranelift_ty = convert_type_to_cranelift_type(ty, ptr_ty)?;
 
                             func_builder.declare_var(
                                 Variable::from_u32(symbol.var_index as u32),
@@ -350,8 +546,14 @@ impl Compiler {
                         arc_alloc: state.arc_alloc,
                         arc_release: state.arc_release,
                         arc_retain: state.arc_retain,
+
+                        diagnostics: state.diagnostics,
+                        dump: state.dump,
+                        str_counter: state.str_counter,
                     };
 
+                    let error_count = func_state.diagnostics.len();
+
                     let result = Self::compile_expr(&mut func_state, block_ast)?;
 
                     if block_ast.get_type() != Ty::Unit {
@@ -360,25 +562,70 @@ impl Compiler {
                         func_state.builder.ins().return_(&[]);
                     }
 
+                    // 函数体中有错误时不再定义该函数, 错误已记录
+                    if func_state.diagnostics.len() > error_count {
+                        state.module.clear_context(&mut ctx);
+                        return Ok(());
+                    }
+
                     func_state.builder.finalize();
 
-                    state
-                        .module
-                        .define_function(func_id, &mut ctx)
-                        .map_or_else(|err| Err(err.to_string()), |_| Ok(()))?;
-                    state.module.clear_context(&mut ctx);
+                    // 10. 并行编译时留到所有函数体生成之后统一编译, 否则立即编译并定义
+                    match state.pending.as_mut() {
+                        Some(pending) => pending.push(PendingFunction::new(
+                            name,
+                            func_id,
+                            ctx,
+                            key,
+                            state.dump,
+                        )),
+                        None => {
+                            Self::define_function(state.module, state.dump, name, func_id, &mut ctx)?
+                        }
+                    }
 
                     return Ok(());
                 }
 
-                todo!()
+                Err(Diagnostic::unsupported(tr!("construct-anonymous-top-level-function")))
+            }
+
+            TypedStatement::Struct { ty, .. } => Self::define_struct(state, ty),
+
+            TypedStatement::Extern {
+                abi,
+                extern_func_name,
+                alias,
+                ty,
+                ..
+            } => {
+                Self::declare_extern(state, abi, extern_func_name, alias, ty)?;
+
+                state.table.borrow_mut().define_func(&alias.value);
+
+                Ok(())
+            }
+
+            TypedStatement::Impl {
+                impl_, for_, block, ..
+            } => {
+                for method in Self::impl_functions(state, impl_, for_.as_ref(), block)? {
+                    Self::compile_top_level_stmt(state, &method)?;
+                }
+
+                Ok(())
             }
 
-            stmt => todo!("impl function 'compile_stmt' {stmt}"),
+            stmt => Err(Diagnostic::unsupported(tr!("construct-top-level-statement", stmt = stmt))),
         }
     }
 
-    pub fn compile_stmt(state: &mut FunctionState, stmt: &TypedStatement) -> Result<Value, String> {
+    pub fn compile_stmt(
+        state: &mut FunctionState<'_, M>,
+        stmt: &TypedStatement,
+    ) -> Result<Value, Diagnostic> {
+        let ptr_ty = state.target_isa.pointer_type();
+
         match stmt {
             TypedStatement::ExpressionStatement(expr) => Self::compile_expr(state, expr),
             TypedStatement::Let {
@@ -390,11 +637,11 @@ impl Compiler {
                 state.retain_if_needed(val, ty);
 
                 let symbol = state.table.borrow_mut().define(&name.value);
-                let cranelift_ty = convert_type_to_cranelift_type(ty);
+                let cranelift_ty = convert_type_to_cranelift_type(ty, ptr_ty)?;
                 state
                     .builder
                     .try_declare_var(Variable::from_u32(symbol.var_index as u32), cranelift_ty)
-                    .map_err(|it| format!("failed to declare variable '{}': {it}", symbol.name))?;
+                    .map_err(|it| tr!("declare-variable-failed", name = symbol.name, error = it))?;
                 state
                     .builder
                     .def_var(Variable::from_u32(symbol.var_index as u32), val);
@@ -406,7 +653,10 @@ impl Compiler {
                 let mut ret_val = state.builder.ins().iconst(types::I64, 0);
 
                 for stmt in it {
-                    ret_val = Self::compile_stmt(state, &stmt)?;
+                    ret_val = match Self::compile_stmt(state, &stmt) {
+                        Ok(val) => val,
+                        Err(err) => state.recover(err),
+                    };
                 }
 
                 let symbols = state.table.borrow().map.clone();
@@ -453,21 +703,7 @@ impl Compiler {
             }
 
             TypedStatement::Struct { ty, .. } => {
-                // 从 Type 中提取字段定义
-                let Ty::Struct { name, fields, .. } = ty else {
-                    return Err(format!("not a struct"));
-                };
-
-                let layout = Self::compile_struct_layout(
-                    state,
-                    name,
-                    &fields
-                        .iter()
-                        .map(|(name, val_ty)| (name.clone(), val_ty.clone()))
-                        .collect::<Vec<(Arc<str>, Ty)>>(),
-                )?;
-
-                state.table.borrow_mut().define_struct_type(name, layout);
+                Self::define_struct(state, ty)?;
 
                 // unit
                 Ok(state.builder.ins().iconst(types::I64, 0))
@@ -480,49 +716,14 @@ impl Compiler {
                 ty,
                 ..
             } => {
-                // 检查 abi (目前只支持c)
-                if abi.value.as_ref() != "C" {
-                    return Err(format!("unsupported abi: {}", abi.value));
-                }
-
-                let Ty::Function {
-                    params_type,
-                    ret_type,
-                    ..
-                } = ty
-                else {
-                    return Err(format!("not a function: {ty}"));
-                };
-
-                let mut cranelift_params = params_type
-                    .iter()
-                    .map(|it| AbiParam::new(convert_type_to_cranelift_type(it)))
-                    .collect::<Vec<_>>();
-
-                // 构造签名
-                let mut extern_func_sig = Signature::new(CALL_CONV);
-
-                extern_func_sig.params.append(&mut cranelift_params);
-
-                extern_func_sig
-                    .returns
-                    .push(AbiParam::new(convert_type_to_cranelift_type(&ret_type)));
-
-                let extern_func_id = state
-                    .module
-                    .declare_function(&extern_func_name.value, Linkage::Import, &extern_func_sig)
-                    .map_err(|e| format!("declare {extern_func_name} failed: {}", e))?;
-
-                // 放进 function_map，方便后面 call
-                state
-                    .function_map
-                    .insert(alias.value.to_string(), extern_func_id);
+                let extern_func_id =
+                    Self::declare_extern(state, abi, extern_func_name, alias, ty)?;
 
                 // 登记进符号表后，立刻 declare
                 let func_symbol = state.table.borrow_mut().define(&alias.value);
                 state.builder.declare_var(
                     Variable::from_u32(func_symbol.var_index as u32),
-                    platform_width_to_int_type(), // 函数指针类型
+                    ptr_ty, // 函数指针类型
                 );
 
                 let func_ref = state
@@ -532,7 +733,7 @@ impl Compiler {
                 let func_addr_val = state
                     .builder
                     .ins()
-                    .func_addr(platform_width_to_int_type(), func_ref);
+                    .func_addr(ptr_ty, func_ref);
 
                 state.builder.def_var(
                     Variable::from_u32(func_symbol.var_index as u32),
@@ -540,7 +741,7 @@ impl Compiler {
                 );
 
                 // unit
-                Ok(state.builder.ins().iconst(platform_width_to_int_type(), 0))
+                Ok(state.builder.ins().iconst(ptr_ty, 0))
             }
 
             TypedStatement::Return { expr, .. } => {
@@ -558,79 +759,259 @@ impl Compiler {
                 impl_, for_, block, ..
             } => {
                 if state.table.borrow_mut().get(&impl_.value).is_none() {
-                    return Err(format!("cannot find type '{impl_}' in this scope"));
+                    return Err(tr!("type-not-found", name = impl_).into());
                 }
 
                 if let Some(for_) = for_
                     && state.table.borrow_mut().get(&for_.value).is_none()
                 {
-                    return Err(format!("cannot find type '{for_}' in this scope"));
+                    return Err(tr!("type-not-found", name = for_).into());
                 }
 
-                let type_name = impl_.value.clone();
+                for method in Self::impl_methods(impl_, block)? {
+                    Self::compile_expr(state, &method)?;
+                }
 
-                let TypedStatement::Block { statements, .. } = &**block else {
-                    unreachable!();
-                };
+                // unit
+                Ok(state.builder.ins().iconst(ptr_ty, 0))
+            }
 
-                for stmt in statements {
-                    let TypedStatement::ExpressionStatement(expr) = stmt else {
-                        continue;
-                    };
+            stmt => Err(Diagnostic::unsupported(tr!("construct-statement", stmt = stmt))),
+        }
+    }
 
-                    let TypedExpression::Function {
-                        name: Some(fn_name),
-                        token,
-                        params,
-                        generics_params,
-                        block,
-                        ret_ty,
-                        ty,
-                    } = expr.clone()
-                    else {
-                        continue;
-                    };
+    /// 由参数与函数体的类型生成函数的 Cranelift 签名
+    fn function_signature(
+        state: &GlobalState<'_, M>,
+        params: &[Box<TypedExpression>],
+        block: &TypedExpression,
+    ) -> Result<Signature, Diagnostic> {
+        let ptr_ty = state.target_isa.pointer_type();
+
+        let mut signature = Signature::new(state.target_isa.default_call_conv());
+
+        for param in params {
+            signature.params.push(AbiParam::new(convert_type_to_cranelift_type(
+                &param.get_type(),
+                ptr_ty,
+            )?));
+        }
 
-                    // mangling
-                    let mut new_name_token = fn_name.clone();
-                    new_name_token.value = format!("{}__{}", type_name, &fn_name.value).into();
-
-                    Self::compile_expr(
-                        state,
-                        &TypedExpression::Function {
-                            token,
-                            name: Some(new_name_token),
-                            params,
-                            generics_params,
-                            block,
-                            ret_ty,
-                            ty,
-                        },
-                    )?;
-                }
+        if block.get_type() != Ty::Unit {
+            signature.returns.push(AbiParam::new(convert_type_to_cranelift_type(
+                &block.get_type(),
+                ptr_ty,
+            )?));
+        }
 
-                // unit
-                Ok(state.builder.ins().iconst(platform_width_to_int_type(), 0))
-            }
+        Ok(signature)
+    }
+
+    /// 声明顶层函数并放进 function_map; 同名函数重复声明时得到同一个 FuncId
+    fn declare_function(
+        state: &mut GlobalState<'_, M>,
+        name: &str,
+        signature: &Signature,
+    ) -> Result<FuncId, Diagnostic> {
+        let func_id = match state.module.declare_function(name, Linkage::Export, signature) {
+            Ok(it) => it,
+            Err(it) => Err(it.to_string())?,
+        };
+
+        state.function_map.insert(name.to_string(), func_id);
+
+        Ok(func_id)
+    }
+
+    /// 提前声明顶层函数, 函数体中可以调用在它之后定义的函数
+    fn declare_top_level_function(
+        state: &mut GlobalState<'_, M>,
+        stmt: &TypedStatement,
+    ) -> Result<(), Diagnostic> {
+        if let TypedStatement::ExpressionStatement(TypedExpression::Function {
+            name: Some(name),
+            params,
+            block,
+            ..
+        }) = stmt
+        {
+            let signature = Self::function_signature(state, params, block)?;
+            Self::declare_function(state, &name.value, &signature)?;
+        }
+
+        Ok(())
+    }
+
+    /// 计算结构体的布局并登记到符号表
+    fn define_struct(state: &impl CompileState, ty: &Ty) -> Result<(), Diagnostic> {
+        // 从 Type 中提取字段定义
+        let Ty::Struct { name, fields, .. } = ty else {
+            return Err(tr!("not-a-struct", name = ty).into());
+        };
+
+        let layout = Self::compile_struct_layout(
+            state,
+            name,
+            &fields
+                .iter()
+                .map(|(name, val_ty)| (name.clone(), val_ty.clone()))
+                .collect::<Vec<(Arc<str>, Ty)>>(),
+        )?;
+
+        state.get_table().borrow_mut().define_struct_type(name, layout);
+
+        Ok(())
+    }
+
+    /// 声明 `extern` 函数并放进 function_map, 方便后面 call
+    fn declare_extern(
+        state: &mut impl CompileState<Module = M>,
+        abi: &Token,
+        extern_func_name: &Token,
+        alias: &Token,
+        ty: &Ty,
+    ) -> Result<FuncId, Diagnostic> {
+        let ptr_ty = state.get_target_isa().pointer_type();
+
+        // 检查 abi (目前只支持c)
+        if abi.value.as_ref() != "C" {
+            return Err(Diagnostic::error(tr!("unsupported-abi", abi = abi.value))
+                .with_code(ErrorCode::E0003));
+        }
+
+        let Ty::Function {
+            params_type,
+            ret_type,
+            ..
+        } = ty
+        else {
+            return Err(tr!("not-a-function", ty = ty).into());
+        };
+
+        let mut cranelift_params = params_type
+            .iter()
+            .map(|it| Ok(AbiParam::new(convert_type_to_cranelift_type(it, ptr_ty)?)))
+            .collect::<Result<Vec<_>, Diagnostic>>()?;
+
+        // 构造签名
+        let mut extern_func_sig = Signature::new(state.get_target_isa().default_call_conv());
 
-            stmt => todo!("impl function 'compile_stmt' {stmt}"),
+        extern_func_sig.params.append(&mut cranelift_params);
+
+        if **ret_type != Ty::Unit {
+            extern_func_sig
+                .returns
+                .push(AbiParam::new(convert_type_to_cranelift_type(&ret_type, ptr_ty)?));
         }
+
+        let extern_func_id = state
+            .get_module()
+            .declare_function(&extern_func_name.value, Linkage::Import, &extern_func_sig)
+            .map_err(|e| tr!("declare-function-failed", name = extern_func_name, error = e))?;
+
+        state
+            .get_function_map()
+            .insert(alias.value.to_string(), extern_func_id);
+
+        Ok(extern_func_id)
     }
 
+    /// 顶层 `impl` 中的方法, 作为顶层函数编译
+    fn impl_functions(
+        state: &GlobalState<'_, M>,
+        impl_: &Token,
+        for_: Option<&Token>,
+        block: &TypedStatement,
+    ) -> Result<Vec<TypedStatement>, Diagnostic> {
+        if state.table.borrow_mut().get(&impl_.value).is_none() {
+            return Err(tr!("type-not-found", name = impl_).into());
+        }
+
+        if let Some(for_) = for_
+            && state.table.borrow_mut().get(&for_.value).is_none()
+        {
+            return Err(tr!("type-not-found", name = for_).into());
+        }
+
+        Ok(Self::impl_methods(impl_, block)?
+            .into_iter()
+            .map(TypedStatement::ExpressionStatement)
+            .collect())
+    }
+
+    /// `impl` 中的方法, 名字改为 `类型::方法`
+    fn impl_methods(
+        impl_: &Token,
+        block: &TypedStatement,
+    ) -> Result<Vec<TypedExpression>, Diagnostic> {
+        let type_name = impl_.value.clone();
+
+        let TypedStatement::Block { statements, .. } = block else {
+            return Err(Diagnostic::unsupported(tr!("construct-impl-body", block = block)));
+        };
+
+        let mut methods = vec![];
+
+        for stmt in statements {
+            let TypedStatement::ExpressionStatement(expr) = stmt else {
+                continue;
+            };
+
+            let TypedExpression::Function {
+                name: Some(fn_name),
+                token,
+                params,
+                generics_params,
+                block,
+                ret_ty,
+                ty,
+            } = expr.clone()
+            else {
+                continue;
+            };
+
+            // mangling
+            let mut new_name_token = fn_name.clone();
+            new_name_token.value = mangle(&type_name, &fn_name.value).into();
+
+            methods.push(TypedExpression::Function {
+                token,
+                name: Some(new_name_token),
+                params,
+                generics_params,
+                block,
+                ret_ty,
+                ty,
+            });
+        }
+
+        Ok(methods)
+    }
+
+    /// 编译表达式, 出错时为诊断信息补充该表达式的位置
     pub fn compile_expr(
-        state: &mut FunctionState,
+        state: &mut FunctionState<'_, M>,
         expr: &TypedExpression,
-    ) -> Result<Value, String> {
+    ) -> Result<Value, Diagnostic> {
+        Self::compile_expr_inner(state, expr).map_err(|it| it.or_token(&expr.token()))
+    }
+
+    fn compile_expr_inner(
+        state: &mut FunctionState<'_, M>,
+        expr: &TypedExpression,
+    ) -> Result<Value, Diagnostic> {
+        let ptr_ty = state.target_isa.pointer_type();
+
         match expr {
             TypedExpression::Int { value, ty, .. } => Ok(state
                 .builder
                 .ins()
-                .iconst(convert_type_to_cranelift_type(ty), int_value_to_imm(value))),
+                .iconst(convert_type_to_cranelift_type(ty, ptr_ty)?, int_value_to_imm(value))),
 
             TypedExpression::Bool { value, ty, .. } => Ok(state
                 .builder
                 .ins()
-                .iconst(convert_type_to_cranelift_type(ty), *value as i64)),
+                .iconst(convert_type_to_cranelift_type(ty, ptr_ty)?, *value as i64)),
 
             TypedExpression::Ident(it, ty) => {
                 let sym = state.table.borrow_mut().get(&it.value);
@@ -649,7 +1030,11 @@ impl Compiler {
                         .get(&var.name.to_string())
                         .map(|it| it.clone())
                         .map_or(
-                            Err(format!("variable `{}` not in data map", var.name)),
+                            Err(Diagnostic::error(tr!(
+                                "variable-not-in-data-map",
+                                name = var.name
+                            ))
+                            .with_code(ErrorCode::E0006)),
                             |it| Ok(it),
                         )?;
 
@@ -660,45 +1045,32 @@ impl Compiler {
                     let val_ptr = state
                         .builder
                         .ins()
-                        .global_value(platform_width_to_int_type(), global_var);
+                        .global_value(ptr_ty, global_var);
 
-                    if *ty == Ty::Str {
+                    // 字符串常量直接存放在数据对象中, 其地址即为值
+                    if *ty == Ty::Str && var.symbol_ty != SymbolTy::Static {
                         return Ok(val_ptr)
                     }
 
                     return Ok(state.builder.ins().load(
-                        convert_type_to_cranelift_type(ty),
+                        convert_type_to_cranelift_type(ty, ptr_ty)?,
                         MemFlags::new(),
                         val_ptr,
                         0,
                     ));
                 }
 
-                Err(format!("undefined variable: {}", it.value))
+                Err(Diagnostic::error(tr!("undefined-variable", name = it.value))
+                    .with_code(ErrorCode::E0007))
             }
 
             TypedExpression::StrLiteral { value, .. } => {
-                let content = value.to_string() + "\0";
-                
-                // 获取当前是第几个字符串 (从一开始计数)
-                let str_count = STR_COUNTER.load(Ordering::Relaxed);
-                STR_COUNTER.fetch_add(1, Ordering::Relaxed);
-
-                let data_id = *state.data_map.entry(content.clone()).or_insert_with(|| {
-                    let name = format!("str_{}_{str_count}", content.len());
-                    let id = state
-                        .module
-                        .declare_data(&name, Linkage::Local, true, false)
-                        .unwrap();
-                    let mut desc = cranelift_module::DataDescription::new();
-
-                    // 使用 Init::Bytes
-                    desc.init = cranelift_module::Init::Bytes {
-                        contents: content.into_bytes().into_boxed_slice(),
-                    };
-                    state.module.define_data(id, &desc).unwrap();
-                    id
-                });
+                let data_id = Self::string_data(
+                    state.module,
+                    state.data_map,
+                    state.str_counter,
+                    value.to_string() + "\0",
+                )?;
 
                 let gv = state
                     .module
@@ -706,7 +1078,7 @@ impl Compiler {
                 Ok(state
                     .builder
                     .ins()
-                    .global_value(platform_width_to_int_type(), gv))
+                    .global_value(ptr_ty, gv))
             }
 
             TypedExpression::FieldAccess(obj, field, _) => {
@@ -716,7 +1088,7 @@ impl Compiler {
                 // 获取对象类型，确保是 struct
                 let obj_ty = obj.get_type();
                 let Ty::Struct { name, .. } = &obj_ty else {
-                    return Err("field access on non-struct type".into());
+                    return Err(tr!("field-access-on-non-struct", ty = obj_ty).into());
                 };
 
                 // 从符号表获取结构体布局
@@ -724,10 +1096,13 @@ impl Compiler {
                     .table
                     .borrow_mut()
                     .get(name)
-                    .ok_or_else(|| format!("undefined struct: {}", name))?
+                    .ok_or_else(|| {
+                        Diagnostic::error(tr!("undefined-struct", name = name))
+                            .with_code(ErrorCode::E0005)
+                    })?
                     .symbol_ty
                 else {
-                    Err(format!("not a struct type"))?
+                    Err(tr!("not-a-struct", name = name))?
                 };
 
                 // 查找字段索引
@@ -735,7 +1110,10 @@ impl Compiler {
                     .fields
                     .iter()
                     .position(|(n, _)| n == &field.value)
-                    .ok_or_else(|| format!("field '{}' not found in struct '{}'", field, name))?; // 类型检查已保证存在，这里只是安全断言
+                    .ok_or_else(|| {
+                        Diagnostic::error(tr!("field-not-found", field = field, name = name))
+                            .with_code(ErrorCode::E0002)
+                    })?; // 类型检查已保证存在，这里只是安全断言
 
                 let offset = layout.offsets[field_idx];
 
@@ -748,7 +1126,7 @@ impl Compiler {
 
                 // 加载字段值
                 let field_ty = &layout.fields[field_idx].1;
-                let cranelift_ty = convert_type_to_cranelift_type(field_ty);
+                let cranelift_ty = convert_type_to_cranelift_type(field_ty, ptr_ty)?;
                 Ok(state
                     .builder
                     .ins()
@@ -761,18 +1139,21 @@ impl Compiler {
                     .borrow_mut()
                     .get(&struct_name.value)
                     .map_or_else(
-                        || Err(format!("undefined struct: {struct_name}")),
+                        || {
+                            Err(Diagnostic::error(tr!("undefined-struct", name = struct_name))
+                                .with_code(ErrorCode::E0005))
+                        },
                         |it| Ok(it.symbol_ty),
                     )?
                 else {
-                    Err(format!("not a struct: {struct_name}"))?
+                    Err(tr!("not-a-struct", name = struct_name))?
                 };
 
                 // 堆分配
                 let size_val = state
                     .builder
                     .ins()
-                    .iconst(platform_width_to_int_type(), layout.size as i64);
+                    .iconst(ptr_ty, layout.size as i64);
                 let struct_ptr = state.emit_alloc(size_val);
 
                 // 写字段
@@ -781,7 +1162,14 @@ impl Compiler {
                         .fields
                         .iter()
                         .position(|(n, _)| n == &field_name.value)
-                        .unwrap();
+                        .ok_or_else(|| {
+                            Diagnostic::error(tr!(
+                                "field-not-found",
+                                field = field_name.value,
+                                name = struct_name
+                            ))
+                            .with_code(ErrorCode::E0002)
+                        })?;
 
                     let offset = layout.offsets[field_idx];
                     let field_ptr = if offset == 0 {
@@ -804,11 +1192,12 @@ impl Compiler {
             TypedExpression::Assign { left, right, .. } => {
                 if let TypedExpression::Ident(ident, _) = &**left {
                     if left.get_type() != right.get_type() {
-                        return Err(format!(
-                            "expected: `{}`, got: `{}`",
-                            left.get_type(),
-                            right.get_type()
-                        ));
+                        return Err(Diagnostic::error(tr!(
+                            "mismatched-types",
+                            expected = left.get_type(),
+                            found = right.get_type()
+                        ))
+                        .with_code(ErrorCode::E0009));
                     }
 
                     let new_val = Self::compile_expr(state, &right)?;
@@ -817,10 +1206,14 @@ impl Compiler {
                         .table
                         .borrow_mut()
                         .get(&ident.value)
-                        .ok_or_else(|| format!("undefined variable `{}`", ident.value))?;
+                        .ok_or_else(|| {
+                            Diagnostic::error(tr!("undefined-variable", name = ident.value))
+                                .with_code(ErrorCode::E0007)
+                        })?;
 
                     if !var_symbol.is_val {
-                        return Err(format!("assign to a type: `{}`", ident.value));
+                        return Err(Diagnostic::error(tr!("assign-to-type", name = ident.value))
+                            .with_code(ErrorCode::E0001));
                     }
 
                     let var = Variable::from_u32(var_symbol.var_index as u32);
@@ -841,7 +1234,7 @@ impl Compiler {
                     // 获取对象类型，确保是 struct
                     let obj_ty = obj.get_type();
                     let Ty::Struct { name, .. } = &obj_ty else {
-                        return Err("field set on non-struct type".into());
+                        return Err(tr!("field-set-on-non-struct", ty = obj_ty).into());
                     };
 
                     // 从符号表获取结构体布局
@@ -849,14 +1242,18 @@ impl Compiler {
                         .table
                         .borrow_mut()
                         .get(name)
-                        .ok_or_else(|| format!("undefined struct: `{}`", name))?;
+                        .ok_or_else(|| {
+                            Diagnostic::error(tr!("undefined-struct", name = name))
+                                .with_code(ErrorCode::E0005)
+                        })?;
 
                     if !sym.is_val {
-                        return Err(format!("assign to a type: `{}`", sym.name));
+                        return Err(Diagnostic::error(tr!("assign-to-type", name = sym.name))
+                            .with_code(ErrorCode::E0001));
                     }
 
                     let SymbolTy::Struct(layout) = sym.symbol_ty else {
-                        Err(format!("not a struct"))?
+                        Err(tr!("not-a-struct", name = name))?
                     };
 
                     // 查找字段索引
@@ -865,7 +1262,8 @@ impl Compiler {
                         .iter()
                         .position(|(n, _)| n == &field.value)
                         .ok_or_else(|| {
-                            format!("field `{}` not found in struct `{}`", field, name)
+                            Diagnostic::error(tr!("field-not-found", field = field, name = name))
+                            .with_code(ErrorCode::E0002)
                         })?; // 类型检查已保证存在，这里只是安全断言
 
                     // 计算字段地址
@@ -881,7 +1279,7 @@ impl Compiler {
 
                     let old_val = if field_ty.need_gc() {
                         Some(state.builder.ins().load(
-                            convert_type_to_cranelift_type(field_ty),
+                            convert_type_to_cranelift_type(field_ty, ptr_ty)?,
                             MemFlags::new(),
                             field_ptr,
                             0,
@@ -906,7 +1304,7 @@ impl Compiler {
 
                     return Ok(new_val);
                 } else {
-                    return Err("assign target must be ident or field".into());
+                    return Err(tr!("invalid-assign-target").into());
                 };
             }
 
@@ -921,11 +1319,12 @@ impl Compiler {
                 for param in params {
                     converted_params.push(AbiParam::new(convert_type_to_cranelift_type(
                         &param.get_type(),
-                    )));
+                        ptr_ty,
+                    )?));
                 }
 
                 let mut ctx = state.module.make_context();
-                ctx.func.signature = Signature::new(CALL_CONV);
+                ctx.func.signature = Signature::new(state.target_isa.default_call_conv());
                 ctx.func.signature.params.append(&mut converted_params);
 
                 if block_ast.get_type() != Ty::Unit {
@@ -934,7 +1333,8 @@ impl Compiler {
                         .returns
                         .push(AbiParam::new(convert_type_to_cranelift_type(
                             &block_ast.get_type(),
-                        )));
+                            ptr_ty,
+                        )?));
                 }
 
                 if let Some(name) = name.as_ref() {
@@ -961,13 +1361,13 @@ impl Compiler {
                     let ref_val = state
                         .builder
                         .ins()
-                        .func_addr(platform_width_to_int_type(), func_ref);
+                        .func_addr(ptr_ty, func_ref);
 
                     // 4. 定义外部作用域的符号
                     let func_symbol = state.table.borrow_mut().define_func(&name);
                     state.builder.declare_var(
                         Variable::from_u32(func_symbol.var_index as u32),
-                        platform_width_to_int_type(),
+                        ptr_ty,
                     );
                     state.builder.def_var(
                         Variable::from_u32(func_symbol.var_index as u32),
@@ -992,7 +1392,7 @@ impl Compiler {
                     let inner_func_symbol = func_symbol_table.borrow_mut().define_func(&name);
                     func_builder.declare_var(
                         Variable::from_u32(inner_func_symbol.var_index as u32),
-                        platform_width_to_int_type(),
+                        ptr_ty,
                     );
 
                     // 在函数内部重新创建FuncRef和对应的Value
@@ -1001,7 +1401,7 @@ impl Compiler {
                         .declare_func_in_func(func_id, &mut func_builder.func);
                     let inner_ref_val = func_builder
                         .ins()
-                        .func_addr(platform_width_to_int_type(), inner_func_ref);
+                        .func_addr(ptr_ty, inner_func_ref);
                     func_builder.def_var(
                         Variable::from_u32(inner_func_symbol.var_index as u32),
                         inner_ref_val, // 使用内部创建的Value
@@ -1011,7 +1411,7 @@ impl Compiler {
                     for (i, param) in params.iter().enumerate() {
                         if let TypedExpression::TypeHint(param_name, _, ty) = &**param {
                             let symbol = func_symbol_table.borrow_mut().define(&param_name.value);
-                            let cranelift_ty = convert_type_to_cranelift_type(ty);
+                            let cranelift_ty = convert_type_to_cranelift_type(ty, ptr_ty)?;
 
                             func_builder.declare_var(
                                 Variable::from_u32(symbol.var_index as u32),
@@ -1037,8 +1437,14 @@ impl Compiler {
                         arc_alloc: state.arc_alloc,
                         arc_release: state.arc_release,
                         arc_retain: state.arc_retain,
+
+                        diagnostics: state.diagnostics,
+                        dump: state.dump,
+                        str_counter: state.str_counter,
                     };
 
+                    let error_count = func_state.diagnostics.len();
+
                     let result = Self::compile_expr(&mut func_state, block_ast)?;
 
                     if block_ast.get_type() != Ty::Unit {
@@ -1047,18 +1453,20 @@ impl Compiler {
                         func_state.builder.ins().return_(&[]);
                     }
 
+                    // 函数体中有错误时不再定义该函数, 错误已记录
+                    if func_state.diagnostics.len() > error_count {
+                        state.module.clear_context(&mut ctx);
+                        return Ok(ref_val);
+                    }
+
                     func_state.builder.finalize();
 
-                    state
-                        .module
-                        .define_function(func_id, &mut ctx)
-                        .map_or_else(|err| Err(err.to_string()), |_| Ok(()))?;
-                    state.module.clear_context(&mut ctx);
+                    Self::define_function(state.module, state.dump, name, func_id, &mut ctx)?;
 
                     return Ok(ref_val);
                 }
 
-                todo!()
+                Err(Diagnostic::unsupported(tr!("construct-anonymous-function")))
             }
 
             TypedExpression::Call {
@@ -1073,7 +1481,7 @@ impl Compiler {
                         ret_type,
                         is_variadic,
                     } => (params_type, ret_type, is_variadic),
-                    _ => unreachable!(),
+                    ty => return Err(tr!("call-non-function", ty = ty).into()),
                 };
 
                 if let TypedExpression::FieldAccess(obj, field, _) = &**func
@@ -1085,7 +1493,7 @@ impl Compiler {
                     }) = fields.get(&field.value)
                 {
                     // 函数名重命名
-                    let func_name = format!("{}__{}", name, field.value);
+                    let func_name = mangle(name, &field.value);
 
                     let call_expr = TypedExpression::Call {
                         token: Token::new(
@@ -1158,32 +1566,33 @@ impl Compiler {
                         .first()
                         .copied()
                         .unwrap_or_else(|| {
-                            state.builder.ins().iconst(platform_width_to_int_type(), 0)
+                            state.builder.ins().iconst(ptr_ty, 0)
                         }));
                 }
 
                 let func_val = Self::compile_expr(state, &func)?;
 
                 // 创建函数签名
-                let mut sig = Signature::new(CALL_CONV);
+                let mut sig = Signature::new(state.target_isa.default_call_conv());
 
                 if *va_arg {
                     for arg in args {
                         sig.params
                             .push(AbiParam::new(convert_type_to_cranelift_type(
                                 &arg.get_type(),
-                            )));
+                                ptr_ty,
+                            )?));
                     }
                 } else {
                     for param_ty in params_type {
                         sig.params
-                            .push(AbiParam::new(convert_type_to_cranelift_type(param_ty)));
+                            .push(AbiParam::new(convert_type_to_cranelift_type(param_ty, ptr_ty)?));
                     }
                 }
 
                 if **ret_ty != Ty::Unit {
                     sig.returns
-                        .push(AbiParam::new(convert_type_to_cranelift_type(ret_ty)));
+                        .push(AbiParam::new(convert_type_to_cranelift_type(ret_ty, ptr_ty)?));
                 }
 
                 // 导入签名
@@ -1197,7 +1606,7 @@ impl Compiler {
 
                 let results = state.builder.inst_results(call_inst);
                 let result = if results.is_empty() {
-                    state.builder.ins().iconst(platform_width_to_int_type(), 0)
+                    state.builder.ins().iconst(ptr_ty, 0)
                 } else {
                     results[0]
                 };
@@ -1220,7 +1629,7 @@ impl Compiler {
 
                 state.builder.append_block_param(
                     end_block,
-                    convert_type_to_cranelift_type(&consequence.get_type()),
+                    convert_type_to_cranelift_type(&consequence.get_type(), ptr_ty)?,
                 );
 
                 let else_block_label = match else_block {
@@ -1263,11 +1672,16 @@ impl Compiler {
             TypedExpression::Infix {
                 op, left, right, ..
             } => compile_infix(state, op.clone(), left, right),
+
+            TypedExpression::Prefix { op, right, .. } => compile_prefix(state, op.clone(), right),
             TypedExpression::Block(_, it, _) => {
                 let mut ret_val = state.builder.ins().iconst(types::I64, 0);
 
                 for stmt in it {
-                    ret_val = Self::compile_stmt(state, &stmt)?;
+                    ret_val = match Self::compile_stmt(state, &stmt) {
+                        Ok(val) => val,
+                        Err(err) => state.recover(err),
+                    };
                 }
 
                 let symbols = state.table.borrow().map.clone();
@@ -1283,24 +1697,42 @@ impl Compiler {
                 Ok(ret_val)
             }
 
-            _ => todo!("impl function 'compile_expr'"),
+            expr => Err(Diagnostic::unsupported(tr!("construct-expression", expr = expr))),
         }
     }
 
-    pub fn compile_program(mut self, program: TypedNode) -> Result<Vec<u8>, String> {
+    /// 将整个程序生成到 module 中, 返回本次编译中的所有错误
+    pub(super) fn codegen(&mut self, mut program: TypedNode) -> Result<(), Vec<Diagnostic>> {
+        if self.options.opt_level.ast_passes() {
+            Optimizer::new(self.options.script_mode)
+                .library(self.library)
+                .optimize(&mut program);
+        }
+
         let statements = match program {
             TypedNode::Program { statements, .. } => statements,
         };
 
-        let mut sig = Signature::new(CALL_CONV);
+        if let Err(err) = self.compile_statements(statements) {
+            self.diagnostics.push(err);
+        }
+
+        if !self.diagnostics.is_empty() {
+            return Err(std::mem::take(&mut self.diagnostics).into_vec());
+        }
+
+        Ok(())
+    }
+
+    fn compile_statements(&mut self, statements: Vec<TypedStatement>) -> Result<(), Diagnostic> {
+        let mut sig = Signature::new(self.target_isa.default_call_conv());
         sig.returns.push(AbiParam::new(types::I32));
 
-        let is_script_mode = read_arg().map_or(false, |it| it.script_mode);
-        if is_script_mode {
+        if self.options.script_mode {
             let func_id = self
                 .module
                 .declare_function("main", Linkage::Export, &sig)
-                .map_err(|e| format!("declare main failed: {}", e))?;
+                .map_err(|e| tr!("declare-function-failed", name = "main", error = e))?;
 
             self.context.func = Function::with_name_signature(UserFuncName::user(0, 0), sig);
             {
@@ -1321,28 +1753,29 @@ impl Compiler {
                     function_map: &mut self.function_map,
                     data_map: &mut self.data_map,
 
-                    table: self.table,
-                    type_table: self.type_table,
+                    table: self.table.clone(),
+                    type_table: self.type_table.clone(),
 
                     arc_alloc: self.arc_alloc,
                     arc_retain: self.arc_retain,
                     arc_release: self.arc_release,
+
+                    diagnostics: &mut self.diagnostics,
+                    dump: &mut self.dump,
+                    str_counter: &mut self.str_counter,
                 };
 
                 for stmt in statements {
-                    ret_val = Self::compile_stmt(&mut state, &stmt)?;
+                    ret_val = match Self::compile_stmt(&mut state, &stmt) {
+                        Ok(val) => val,
+                        Err(err) => state.recover(err),
+                    };
                 }
 
                 state.builder.ins().return_(&[ret_val]);
 
-                #[cfg(debug_assertions)]
-                {
-                    let func_ref = &state.builder.func;
-                    println!("=== before finalize:\n{}", {
-                        let mut s = String::new();
-                        cranelift::codegen::write_function(&mut s, func_ref).unwrap();
-                        s
-                    });
+                if !state.diagnostics.is_empty() {
+                    return Ok(());
                 }
 
                 state.builder.finalize();
@@ -1356,14 +1789,17 @@ impl Compiler {
                         use std::fmt::Write;
                         writeln!(msg, "verifier: {}", e).unwrap();
                     }
-                    return Err(format!("verifier errors:\n{}", msg));
+                    return Err(Diagnostic::error(tr!("verifier-errors")).with_note(msg));
                 }
             }
 
-            self.module
-                .define_function(func_id, &mut self.context)
-                .map_err(|e| format!("define main failed: {}", e))?;
-            self.context.clear();
+            Self::define_function(
+                &mut self.module,
+                &mut self.dump,
+                "main",
+                func_id,
+                &mut self.context,
+            )?;
         } else {
             let mut state = GlobalState {
                 target_isa: self.target_isa.clone(),
@@ -1371,25 +1807,76 @@ impl Compiler {
                 function_map: &mut self.function_map,
                 data_map: &mut self.data_map,
 
-                table: self.table,
-                type_table: self.type_table,
+                table: self.table.clone(),
+                type_table: self.type_table.clone(),
 
                 arc_alloc: self.arc_alloc,
                 arc_retain: self.arc_retain,
                 arc_release: self.arc_release,
+
+                diagnostics: &mut self.diagnostics,
+                dump: &mut self.dump,
+                str_counter: &mut self.str_counter,
+                cache: self.cache.as_mut(),
+                pending: Some(vec![]),
             };
 
+            // 1. 按顺序定义常量、结构体与 extern 函数, 函数 (包括方法) 留到之后
+            let mut functions = vec![];
+
             for stmt in statements {
-                if !Self::is_top_level_stmt(&stmt) {
+                if !Self::is_item(&stmt) {
                     continue;
                 }
 
-                Self::compile_top_level_stmt(&mut state, &stmt)?;
+                let result = match stmt {
+                    TypedStatement::ExpressionStatement(TypedExpression::Function { .. }) => {
+                        functions.push(stmt);
+                        Ok(())
+                    }
+                    TypedStatement::Impl {
+                        ref impl_,
+                        ref for_,
+                        ref block,
+                        ..
+                    } => Self::impl_functions(&state, impl_, for_.as_ref(), block)
+                        .map(|it| functions.extend(it)),
+                    stmt => Self::compile_top_level_stmt(&mut state, &stmt),
+                };
+
+                if let Err(err) = result {
+                    state.diagnostics.push(err);
+                }
+            }
+
+            // 2. 声明所有函数
+            functions.retain(|it| match Self::declare_top_level_function(&mut state, it) {
+                Ok(()) => true,
+                Err(err) => {
+                    state.diagnostics.push(err);
+                    false
+                }
+            });
+
+            // 3. 按顺序生成函数体的 IR
+            for function in &functions {
+                if let Err(err) = Self::compile_top_level_stmt(&mut state, function) {
+                    state.diagnostics.push(err);
+                }
+            }
+
+            // 4. 在线程池中编译为机器码, 再按声明顺序定义, 目标文件与线程数无关
+            let mut pending = state.pending.take().unwrap_or_default();
+            compile_all(&*state.target_isa, &mut pending, self.options.jobs);
+
+            for function in pending {
+                if let Err(err) = Self::define_compiled(&mut state, function) {
+                    state.diagnostics.push(err);
+                }
             }
         }
 
-        let obj = self.module.finish();
-        Ok(obj.emit().unwrap().to_vec())
+        Ok(())
     }
 }
 
@@ -1398,89 +1885,345 @@ mod tests {
     use std::{
         cell::RefCell,
         path::Path,
+        process::{Command, ExitStatus},
         rc::Rc,
         sync::{Arc, Mutex},
     };
 
-    use ant_lexer::Lexer;
-    use ant_parser::Parser;
-
-    use ant_type_checker::{TypeChecker, table::TypeTable};
+    use ant_type_checker::table::TypeTable;
 
     use crate::{
-        compiler::{Compiler, compile_to_executable, create_target_isa, table::SymbolTable},
-        monomorphizer::Monomorphizer,
+        compiler::{
+            CompiledProgram, Compiler, compile_to_executable, compile_to_library,
+            create_target_isa, header::generate_header, table::SymbolTable,
+        },
+        session::{CompileOptions, CrateType, LinkMode, TargetCpu, analyze_source},
     };
 
-    #[test]
-    fn simple_program() {
-        let file: std::sync::Arc<str> = "__simple_program__".into();
+    /// 分析并编译 `source`, 得到目标文件
+    fn compile(file: &str, source: &str, options: &CompileOptions) -> CompiledProgram {
+        let type_table = Arc::new(Mutex::new(TypeTable::new().init()));
+        let typed_node = analyze_source(file.into(), source.into(), type_table.clone()).unwrap();
 
-        // 创建目标 ISA
-        let target_isa = create_target_isa();
+        Compiler::new(
+            create_target_isa(options).unwrap(),
+            Rc::new(RefCell::new(SymbolTable::new())),
+            type_table,
+            options.clone(),
+        )
+        .unwrap()
+        .compile_program(typed_node)
+        .unwrap()
+    }
 
-        // 解析ast
-        let tokens = (&mut Lexer::new(
-            r#"
+    /// 把目标文件链接为 `exe` 并运行
+    fn link_and_run(object: &[u8], exe: &Path, options: &CompileOptions) -> ExitStatus {
+        compile_to_executable(&[object], exe, options).unwrap();
+
+        Command::new(exe).status().unwrap()
+    }
+
+    #[test]
+    fn simple_program() {
+        let source = r#"
             func main() -> i32 {
                 extern "C" func printf(s: str, ...) -> i32;
-            
+
                 struct A {}
-                
+
                 impl A {
                     func f() -> i64 {
                         917813i64
                     }
                 }
-                    
+
                 let o = new A {};
-                    
+
                 printf("%lld\n", o.f())
-                    
+
                 printf("end\n");
             }
-            "#
-            .into(),
-            file.clone(),
-        ))
-            .get_tokens();
+        "#;
+
+        let options = CompileOptions::new().file_name("__simple_program__");
+        let program = compile("__simple_program__", source, &options);
+
+        let dir = tempfile::tempdir().unwrap();
+        link_and_run(
+            &program.object,
+            &dir.path().join("test_program.exe"),
+            &options,
+        );
+    }
+
+    #[test]
+    fn cross_compile_object() {
+        let source = "func main() -> i32 {\n    0i32\n}";
+
+        // (目标平台, ELF e_machine)
+        for (target, machine) in [
+            ("aarch64-unknown-linux-gnu", 183u16),
+            ("riscv64gc-unknown-linux-gnu", 243u16),
+        ] {
+            let options = CompileOptions::new().target(Some(target.to_string()));
+            let program = compile("<cross>", source, &options);
+
+            assert_eq!(&program.object[..4], b"\x7fELF");
+            assert_eq!(
+                u16::from_le_bytes([program.object[18], program.object[19]]),
+                machine
+            );
+        }
+
+        let options = CompileOptions::new().target(Some("not-a-target".to_string()));
+        assert!(create_target_isa(&options).is_err());
+    }
+
+    #[test]
+    fn target_cpu_and_codegen_flags() {
+        let options = CompileOptions::new()
+            .target(Some("x86_64-unknown-linux-gnu".to_string()))
+            .target_cpu(Some(TargetCpu::X86_64V3))
+            .codegen_flags([("preserve_frame_pointers", "true"), ("has_lzcnt", "false")]);
+
+        let isa = create_target_isa(&options).unwrap();
+        let isa_flag = |name: &str| {
+            isa.isa_flags()
+                .iter()
+                .find(|it| it.name == name)
+                .and_then(|it| it.as_bool())
+        };
+
+        assert!(isa.flags().preserve_frame_pointers());
+        assert_eq!(isa_flag("has_avx2"), Some(true));
+        assert_eq!(isa_flag("has_lzcnt"), Some(false));
+
+        for options in [
+            options.clone().codegen_flags([("no_such_flag", "true")]),
+            options.clone().target_cpu(Some(TargetCpu::Native)),
+            options.target(Some("aarch64-unknown-linux-gnu".to_string())),
+        ] {
+            assert!(create_target_isa(&options).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn link_shared_libraries_and_report_failures() {
+        let source = r#"
+            func main() -> i32 {
+                extern "C" func pthread_self() -> u64;
+
+                pthread_self();
+                0i32
+            }
+        "#;
+
+        let options = CompileOptions::new()
+            .link_mode(Some(LinkMode::Dynamic))
+            .libs(["m", "pthread"]);
+
+        let program = compile("<link>", source, &options);
+
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("link_test");
+
+        assert!(link_and_run(&program.object, &exe, &options).success());
+
+        let missing = options.libs(["ant_no_such_library"]);
+        assert!(compile_to_executable(&[&program.object], &exe, &missing).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn call_library_from_c() {
+        let source = r#"
+            func add(a: i64, b: i64) -> i64 {
+                a + b
+            }
+
+            func greeting() -> str {
+                "hi"
+            }
+        "#;
 
-        let node = (&mut Parser::new(tokens)).parse_program().unwrap();
+        let dir = tempfile::tempdir().unwrap();
 
         let type_table = Arc::new(Mutex::new(TypeTable::new().init()));
+        let typed_node = analyze_source("<lib>".into(), source.into(), type_table).unwrap();
+
+        let header = generate_header(&typed_node, "lib").unwrap();
+        assert!(header.contains("int64_t add(int64_t a, int64_t b);"));
+        assert!(header.contains("const char* greeting(void);"));
+        std::fs::write(dir.path().join("lib.h"), header).unwrap();
+
+        for crate_type in [CrateType::Staticlib, CrateType::Cdylib] {
+            let options = CompileOptions::new().crate_type(crate_type);
+            let program = compile("<lib>", source, &options);
+
+            let lib = dir.path().join(match crate_type {
+                CrateType::Staticlib => "liblib.a",
+                _ => "liblib.so",
+            });
+
+            compile_to_library(&program.object, &lib, &options).unwrap();
+            assert!(lib.exists());
+        }
 
-        let mut typed_node = (&mut TypeChecker::new(type_table.clone()))
-            .check_node(node)
+        // 用静态库链接一个 C 程序
+        let main_c = dir.path().join("main.c");
+        std::fs::write(
+            &main_c,
+            r#"
+            #include <string.h>
+            #include "lib.h"
+
+            int main(void) {
+                return add(40, 2) == 42 && strcmp(greeting(), "hi") == 0 ? 0 : 1;
+            }
+            "#,
+        )
+        .unwrap();
+
+        let exe = dir.path().join("main");
+        let status = Command::new("cc")
+            .arg("-o")
+            .arg(&exe)
+            .arg(&main_c)
+            .arg(dir.path().join("liblib.a"))
+            .status()
             .unwrap();
 
-        // 创建编译器实例
-        let table = SymbolTable::new();
+        assert!(status.success());
+        assert!(Command::new(&exe).status().unwrap().success());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn pie_executable_without_text_relocations() {
+        let source = r#"
+            const greeting = "hello";
+            const answer = 42i32;
 
-        let compiler = Compiler::new(
-            target_isa,
-            "__simple_program__".into(),
-            Rc::new(RefCell::new(table)),
-            type_table.clone(),
+            func main() -> i32 {
+                extern "C" func puts(s: str) -> i32;
+
+                puts(greeting);
+                puts("world");
+                answer - 42i32
+            }
+        "#;
+
+        let dir = tempfile::tempdir().unwrap();
+
+        // ELF 头中的 e_type: 2 为 ET_EXEC, 3 为 ET_DYN (PIE)
+        for (pie, e_type) in [(None, 3u16), (Some(false), 2u16)] {
+            // `-z text`: 存在文本重定位时链接失败
+            let options = CompileOptions::new().pie(pie).link_args(["-Wl,-z,text"]);
+            let program = compile("<pie>", source, &options);
+
+            let exe = dir.path().join("pie_test");
+            assert!(link_and_run(&program.object, &exe, &options).success());
+
+            let elf = std::fs::read(&exe).unwrap();
+            assert_eq!(u16::from_le_bytes([elf[16], elf[17]]), e_type);
+        }
+    }
+
+    #[test]
+    fn incremental_cache_reuses_unchanged_functions() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = CompileOptions::new().incremental(Some(dir.path().join("antc-cache")));
+
+        let fib = "func fib(n: i32) -> i32 {\n    if n < 2i32 { n } else { fib(n - 1i32) + fib(n - 2i32) }\n}\n";
+
+        let main = |body: &str| format!("func main() -> i32 {{\n    {body}\n}}\n");
+
+        let first = compile(
+            "<incremental>",
+            &format!("{fib}\n{}", main("fib(10i32)")),
+            &options,
         );
+        assert!(first.cached.is_empty());
 
-        (&mut Monomorphizer::new())
-            .monomorphize(&mut typed_node)
-            .unwrap();
+        // 没有变化: 全部使用缓存, 生成的目标文件相同
+        let mut second = compile(
+            "<incremental>",
+            &format!("{fib}\n{}", main("fib(10i32)")),
+            &options,
+        );
+        second.cached.sort();
+        assert_eq!(second.cached, ["fib", "main"]);
+        assert_eq!(first.object, second.object);
+
+        // 只修改 `main`, 并在 `fib` 之前插入空行: `fib` 仍然命中
+        let third = compile(
+            "<incremental>",
+            &format!("\n\n{fib}\n{}", main("fib(9i32) + 21i32")),
+            &options,
+        );
+        assert_eq!(third.cached, ["fib"]);
+
+        // 新增无关的常量与函数: 已有的函数都不受影响
+        let mut fourth = compile(
+            "<incremental>",
+            &format!(
+                "\n\n{fib}\nconst limit = 3i32;\n\nfunc unused() -> i32 {{\n    limit\n}}\n\n{}",
+                main("fib(9i32) + 21i32")
+            ),
+            &options,
+        );
+        fourth.cached.sort();
+        assert_eq!(fourth.cached, ["fib", "main"]);
 
-        // 编译程序
-        match compiler.compile_program(typed_node) {
-            Ok(object_code) => {
-                println!(
-                    "Compilation successful! Object code size: {} bytes",
-                    object_code.len()
-                );
+        #[cfg(unix)]
+        {
+            let status = link_and_run(&third.object, &dir.path().join("incremental"), &options);
+            assert_eq!(status.code(), Some(55));
+        }
+    }
 
-                // 编译到可执行文件
-                compile_to_executable(&object_code, Path::new("test_program.exe")).unwrap();
+    #[test]
+    fn parallel_codegen_is_deterministic() {
+        // `main` 调用在它之后定义的函数
+        let source = r#"
+            func main() -> i32 {
+                add(square(5i32), double(8i32)) + 1i32
             }
-            Err(e) => {
-                panic!("Compilation failed: {}", e);
+
+            func add(a: i32, b: i32) -> i32 {
+                a + b
             }
+
+            func square(n: i32) -> i32 {
+                n * n
+            }
+
+            func double(n: i32) -> i32 {
+                n + n
+            }
+        "#;
+
+        let compile_with = |jobs: usize| {
+            compile(
+                "<parallel>",
+                source,
+                &CompileOptions::new().jobs(Some(jobs)),
+            )
+        };
+
+        let serial = compile_with(1);
+        assert_eq!(serial.object, compile_with(4).object);
+
+        #[cfg(unix)]
+        {
+            let dir = tempfile::tempdir().unwrap();
+            let status = link_and_run(
+                &serial.object,
+                &dir.path().join("parallel"),
+                &CompileOptions::new(),
+            );
+            assert_eq!(status.code(), Some(42));
         }
     }
 }
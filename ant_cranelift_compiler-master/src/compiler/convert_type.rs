@@ -1,13 +1,17 @@
 use ant_type_checker::ty::{IntTy, Ty};
 use cranelift::prelude::types;
 
-use crate::compiler::imm::platform_width_to_int_type;
+use crate::{diagnostic::Diagnostic, tr};
 
-pub fn convert_type_to_cranelift_type(ty: &Ty) -> types::Type {
-    match ty {
+/// `pointer_type`: 目标平台的指针类型, 用于 usize/isize、引用类型与函数指针
+pub fn convert_type_to_cranelift_type(
+    ty: &Ty,
+    pointer_type: types::Type,
+) -> Result<types::Type, Diagnostic> {
+    Ok(match ty {
         Ty::IntTy(ty) => match ty {
-            IntTy::USize => platform_width_to_int_type(),
-            IntTy::ISize => platform_width_to_int_type(),
+            IntTy::USize => pointer_type,
+            IntTy::ISize => pointer_type,
             IntTy::I64 => types::I64,
             IntTy::I32 => types::I32,
             IntTy::I16 => types::I16,
@@ -18,10 +22,37 @@ pub fn convert_type_to_cranelift_type(ty: &Ty) -> types::Type {
             IntTy::U8 => types::I8,
         }
         Ty::Bool => types::I8, // cranelift 没有比 i8 更小的类型了
-        Ty::Struct { .. } => platform_width_to_int_type(),
-        Ty::Str => platform_width_to_int_type(),
-        Ty::Function { .. } => platform_width_to_int_type(), // 以函数指针处理
+        Ty::Struct { .. } => pointer_type,
+        Ty::Str => pointer_type,
+        Ty::Function { .. } => pointer_type, // 以函数指针处理
         Ty::Unit => types::I8,
-        _ => todo!("impl ty {ty}"),
-    }
-}
\ No newline at end of file
+        _ => Err(Diagnostic::unsupported(tr!("construct-type", ty = ty)))?,
+    })
+}
+
+/// 生成 C 头文件时使用的类型, 与 [`convert_type_to_cranelift_type`] 的表示一致
+///
+/// 结构体是带引用计数的堆对象, 以指针传递
+pub fn convert_type_to_c_type(ty: &Ty) -> Result<String, Diagnostic> {
+    Ok(match ty {
+        Ty::IntTy(ty) => match ty {
+            IntTy::USize => "size_t",
+            IntTy::ISize => "intptr_t",
+            IntTy::I64 => "int64_t",
+            IntTy::I32 => "int32_t",
+            IntTy::I16 => "int16_t",
+            IntTy::I8 => "int8_t",
+            IntTy::U64 => "uint64_t",
+            IntTy::U32 => "uint32_t",
+            IntTy::U16 => "uint16_t",
+            IntTy::U8 => "uint8_t",
+        }
+        .to_string(),
+        Ty::Bool => "bool".to_string(),
+        Ty::Struct { name, .. } => format!("{name}*"),
+        Ty::Str => "const char*".to_string(),
+        Ty::Function { .. } => "void*".to_string(),
+        Ty::Unit => "void".to_string(),
+        _ => Err(Diagnostic::unsupported(tr!("construct-type", ty = ty)))?,
+    })
+}
@@ -2,177 +2,290 @@ use std::sync::Arc;
 
 use ant_ast::expr::IntValue;
 use ant_type_checker::{
-    ty::Ty,
+    ty::{IntTy, Ty},
     typed_ast::{GetType, typed_expr::TypedExpression},
 };
 use cranelift::prelude::{InstBuilder, IntCC, Value, types};
+use cranelift_module::Module;
 
-use crate::compiler::{Compiler, FunctionState};
+use crate::{
+    compiler::{Compiler, FunctionState, imm::int_value_to_int_type},
+    diagnostic::Diagnostic,
+    tr,
+};
+
+type OpFunc<M> = fn(&mut FunctionState<'_, M>, Value, Value) -> Value;
 
-macro_rules! four_fundamental_operations {
+macro_rules! binary {
     ($op:ident) => {
-        (|state, x, y| state.builder.ins().$op(x, y)) as OpFunc
+        (|state, x, y| state.builder.ins().$op(x, y)) as OpFunc<M>
     };
 }
 
 macro_rules! cmp {
     ($op:expr) => {
-        (|state, x, y| state.builder.ins().icmp($op, x, y)) as OpFunc
+        (|state, x, y| state.builder.ins().icmp($op, x, y)) as OpFunc<M>
     };
 }
 
-macro_rules! const_eval_op {
-    ($state:expr, $ty:expr, $op:expr) => {
-        $state.builder.ins().iconst($ty, $op)
-    };
+/// 有符号整数使用 `sdiv` `srem` `sshr` 与有符号比较, 无符号整数使用对应的无符号版本
+fn is_signed(ty: &IntTy) -> bool {
+    matches!(
+        ty,
+        IntTy::I64 | IntTy::I32 | IntTy::I16 | IntTy::I8 | IntTy::ISize
+    )
 }
 
-pub fn compile_infix_iadd(state: &mut FunctionState<'_>, left: IntValue, right: IntValue) -> Value {
-    match (left, right) {
-        (IntValue::I64(l), IntValue::I64(r)) => const_eval_op!(state, types::I64, l + r),
-        (IntValue::I32(l), IntValue::I32(r)) => const_eval_op!(state, types::I32, (l + r) as i64),
-        (IntValue::I16(l), IntValue::I16(r)) => const_eval_op!(state, types::I16, (l + r) as i64),
-        (IntValue::I8(l), IntValue::I8(r)) => const_eval_op!(state, types::I8, (l + r) as i64),
-        (IntValue::U64(l), IntValue::U64(r)) => const_eval_op!(state, types::I64, (l + r) as i64),
-        (IntValue::U32(l), IntValue::U32(r)) => const_eval_op!(state, types::I32, (l + r) as i64),
-        (IntValue::U16(l), IntValue::U16(r)) => const_eval_op!(state, types::I16, (l + r) as i64),
-        (IntValue::U8(l), IntValue::U8(r)) => const_eval_op!(state, types::I8, (l + r) as i64),
-        _ => todo!(),
-    }
-}
+/// 在编译期对两个同类型的整数字面量求值, 与运行时的结果一致: 算术按位宽回绕, 移位量按位宽取模
+///
+/// 除数为零与有符号除法溢出留到运行时 (触发 trap), 不折叠
+pub fn fold_int_infix<M: Module>(
+    state: &mut FunctionState<'_, M>,
+    op: &str,
+    left: IntValue,
+    right: IntValue,
+) -> Option<Value> {
+    let int_ty = int_value_to_int_type(&left, state.target_isa.pointer_type());
 
-pub fn compile_infix_isub(state: &mut FunctionState<'_>, left: IntValue, right: IntValue) -> Value {
-    match (left, right) {
-        (IntValue::I64(l), IntValue::I64(r)) => const_eval_op!(state, types::I64, l - r),
-        (IntValue::I32(l), IntValue::I32(r)) => const_eval_op!(state, types::I32, (l - r) as i64),
-        (IntValue::I16(l), IntValue::I16(r)) => const_eval_op!(state, types::I16, (l - r) as i64),
-        (IntValue::I8(l), IntValue::I8(r)) => const_eval_op!(state, types::I8, (l - r) as i64),
-        (IntValue::U64(l), IntValue::U64(r)) => const_eval_op!(state, types::I64, (l - r) as i64),
-        (IntValue::U32(l), IntValue::U32(r)) => const_eval_op!(state, types::I32, (l - r) as i64),
-        (IntValue::U16(l), IntValue::U16(r)) => const_eval_op!(state, types::I16, (l - r) as i64),
-        (IntValue::U8(l), IntValue::U8(r)) => const_eval_op!(state, types::I8, (l - r) as i64),
-        _ => todo!(),
+    macro_rules! fold {
+        ($($variant:ident),*) => {
+            match (left, right) {
+                $(
+                    (IntValue::$variant(l), IntValue::$variant(r)) => match op {
+                        "+" => (int_ty, l.wrapping_add(r) as i64),
+                        "-" => (int_ty, l.wrapping_sub(r) as i64),
+                        "*" => (int_ty, l.wrapping_mul(r) as i64),
+                        "/" => (int_ty, l.checked_div(r)? as i64),
+                        "%" => (int_ty, l.checked_rem(r)? as i64),
+                        "&" => (int_ty, (l & r) as i64),
+                        "|" => (int_ty, (l | r) as i64),
+                        "^" => (int_ty, (l ^ r) as i64),
+                        "<<" => (int_ty, l.wrapping_shl(r as u32) as i64),
+                        ">>" => (int_ty, l.wrapping_shr(r as u32) as i64),
+                        "==" => (types::I8, (l == r) as i64),
+                        "!=" => (types::I8, (l != r) as i64),
+                        "<" => (types::I8, (l < r) as i64),
+                        "<=" => (types::I8, (l <= r) as i64),
+                        ">" => (types::I8, (l > r) as i64),
+                        ">=" => (types::I8, (l >= r) as i64),
+                        _ => return None,
+                    },
+                )*
+                _ => return None,
+            }
+        };
     }
-}
 
-pub fn compile_infix_imul(state: &mut FunctionState<'_>, left: IntValue, right: IntValue) -> Value {
-    match (left, right) {
-        (IntValue::I64(l), IntValue::I64(r)) => const_eval_op!(state, types::I64, l * r),
-        (IntValue::I32(l), IntValue::I32(r)) => const_eval_op!(state, types::I32, (l * r) as i64),
-        (IntValue::I16(l), IntValue::I16(r)) => const_eval_op!(state, types::I16, (l * r) as i64),
-        (IntValue::I8(l), IntValue::I8(r)) => const_eval_op!(state, types::I8, (l * r) as i64),
-        (IntValue::U64(l), IntValue::U64(r)) => const_eval_op!(state, types::I64, (l * r) as i64),
-        (IntValue::U32(l), IntValue::U32(r)) => const_eval_op!(state, types::I32, (l * r) as i64),
-        (IntValue::U16(l), IntValue::U16(r)) => const_eval_op!(state, types::I16, (l * r) as i64),
-        (IntValue::U8(l), IntValue::U8(r)) => const_eval_op!(state, types::I8, (l * r) as i64),
-        _ => todo!(),
-    }
-}
+    let (ty, value) = fold!(I8, I16, I32, I64, ISize, U8, U16, U32, U64, USize);
 
-pub fn compile_infix_ieq(state: &mut FunctionState<'_>, left: IntValue, right: IntValue) -> Value {
-    match (left, right) {
-        (IntValue::I64(l), IntValue::I64(r)) => const_eval_op!(state, types::I8, (l == r) as i64),
-        (IntValue::I32(l), IntValue::I32(r)) => const_eval_op!(state, types::I8, (l == r) as i64),
-        (IntValue::I16(l), IntValue::I16(r)) => const_eval_op!(state, types::I8, (l == r) as i64),
-        (IntValue::I8(l), IntValue::I8(r)) => const_eval_op!(state, types::I8, (l == r) as i64),
-        (IntValue::U64(l), IntValue::U64(r)) => const_eval_op!(state, types::I8, (l == r) as i64),
-        (IntValue::U32(l), IntValue::U32(r)) => const_eval_op!(state, types::I8, (l == r) as i64),
-        (IntValue::U16(l), IntValue::U16(r)) => const_eval_op!(state, types::I8, (l == r) as i64),
-        (IntValue::U8(l), IntValue::U8(r)) => const_eval_op!(state, types::I8, (l == r) as i64),
-        _ => todo!(),
-    }
+    Some(state.builder.ins().iconst(ty, value))
 }
 
-pub fn compile_infix_ineq(state: &mut FunctionState<'_>, left: IntValue, right: IntValue) -> Value {
-    match (left, right) {
-        (IntValue::I64(l), IntValue::I64(r)) => const_eval_op!(state, types::I8, (l != r) as i64),
-        (IntValue::I32(l), IntValue::I32(r)) => const_eval_op!(state, types::I8, (l != r) as i64),
-        (IntValue::I16(l), IntValue::I16(r)) => const_eval_op!(state, types::I8, (l != r) as i64),
-        (IntValue::I8(l), IntValue::I8(r)) => const_eval_op!(state, types::I8, (l != r) as i64),
-        (IntValue::U64(l), IntValue::U64(r)) => const_eval_op!(state, types::I8, (l != r) as i64),
-        (IntValue::U32(l), IntValue::U32(r)) => const_eval_op!(state, types::I8, (l != r) as i64),
-        (IntValue::U16(l), IntValue::U16(r)) => const_eval_op!(state, types::I8, (l != r) as i64),
-        (IntValue::U8(l), IntValue::U8(r)) => const_eval_op!(state, types::I8, (l != r) as i64),
-        _ => todo!(),
+/// `&&` 与 `||`: 左侧已能决定结果时不再计算右侧, 结果作为 end_block 的参数传出
+fn compile_short_circuit<M: Module>(
+    state: &mut FunctionState<'_, M>,
+    op: &str,
+    left: &Box<TypedExpression>,
+    right: &Box<TypedExpression>,
+) -> Result<Value, Diagnostic> {
+    let right_block = state.builder.create_block();
+    let end_block = state.builder.create_block();
+
+    state.builder.append_block_param(end_block, types::I8);
+
+    let lval = Compiler::compile_expr(state, left)?;
+
+    // `&&` 左侧为假、`||` 左侧为真时直接以左侧的值结束
+    if op == "&&" {
+        state
+            .builder
+            .ins()
+            .brif(lval, right_block, &[], end_block, &[lval]);
+    } else {
+        state
+            .builder
+            .ins()
+            .brif(lval, end_block, &[lval], right_block, &[]);
     }
+
+    state.builder.switch_to_block(right_block);
+    state.builder.seal_block(right_block);
+
+    let rval = Compiler::compile_expr(state, right)?;
+    state.builder.ins().jump(end_block, &[rval]);
+
+    state.builder.switch_to_block(end_block);
+    state.builder.seal_block(end_block);
+
+    Ok(state.builder.block_params(end_block)[0])
 }
 
-pub fn compile_infix(
-    state: &mut FunctionState<'_>,
+pub fn compile_infix<M: Module>(
+    state: &mut FunctionState<'_, M>,
     op: Arc<str>,
     left: &Box<TypedExpression>,
     right: &Box<TypedExpression>,
-) -> Result<Value, String> {
-    #[rustfmt::skip]
-    let mut non_const_handler = |
-        left: &Box<TypedExpression>,
-        right: &Box<TypedExpression>,
-        op: &str
-    | {
-        let lval = Compiler::compile_expr(state, &left)?;
-        let rval = Compiler::compile_expr(state, &right)?;
-
-        match (left.get_type(), right.get_type()) {
-            (Ty::IntTy(_), Ty::IntTy(_)) => {
-                type OpFunc = fn(&mut FunctionState<'_>, Value, Value) -> Value;
-
-                let op_func: OpFunc = match op {
-                    "+" => four_fundamental_operations!(iadd),
-                    "-" => four_fundamental_operations!(isub),
-                    "*" => four_fundamental_operations!(imul),
-                    "/" => four_fundamental_operations!(fdiv),
-                    ">" => cmp!(IntCC::SignedGreaterThan),
-                    "<" => cmp!(IntCC::SignedLessThan),
-                    "==" => cmp!(IntCC::Equal),
-                    "!=" => cmp!(IntCC::NotEqual),
-                    _ => todo!("todo op {op}"),
-                };
-
-                Ok(op_func(state, lval, rval))
-            }
+) -> Result<Value, Diagnostic> {
+    if let ("&&" | "||", Ty::Bool, Ty::Bool) = (op.as_ref(), left.get_type(), right.get_type()) {
+        return compile_short_circuit(state, &op, left, right);
+    }
+
+    // 两侧都是同类型整数字面量时在编译期求值, 否则在运行时计算
+    let folded = match (&**left, &**right) {
+        (TypedExpression::Int { value: lval, .. }, TypedExpression::Int { value: rval, .. }) => {
+            fold_int_infix(state, &op, *lval, *rval)
+        }
+        _ => None,
+    };
 
-            (Ty::Bool, Ty::Bool) => {
-                type OpFunc = fn(&mut FunctionState<'_>, Value, Value) -> Value;
+    if let Some(it) = folded {
+        return Ok(it);
+    }
 
-                let op_func: OpFunc = match op {
-                    "==" => cmp!(IntCC::Equal),
-                    "!=" => cmp!(IntCC::NotEqual),
-                    _ => todo!("todo op {op}"),
-                };
+    let lval = Compiler::compile_expr(state, left)?;
+    let rval = Compiler::compile_expr(state, right)?;
 
-                Ok(op_func(state, lval, rval))
-            }
+    let unsupported = || {
+        Diagnostic::unsupported(tr!(
+            "construct-operator",
+            op = op,
+            left = left.get_type(),
+            right = right.get_type()
+        ))
+    };
 
-            (lty, rty) => todo!("impl {left} {op} {right}. left_ty: {lty}, right_ty: {rty}"),
+    let op_func: OpFunc<M> = match (left.get_type(), right.get_type()) {
+        (Ty::IntTy(ty), Ty::IntTy(_)) => {
+            let signed = is_signed(&ty);
+
+            match (op.as_ref(), signed) {
+                ("+", _) => binary!(iadd),
+                ("-", _) => binary!(isub),
+                ("*", _) => binary!(imul),
+                ("/", true) => binary!(sdiv),
+                ("/", false) => binary!(udiv),
+                ("%", true) => binary!(srem),
+                ("%", false) => binary!(urem),
+                ("&", _) => binary!(band),
+                ("|", _) => binary!(bor),
+                ("^", _) => binary!(bxor),
+                ("<<", _) => binary!(ishl),
+                (">>", true) => binary!(sshr),
+                (">>", false) => binary!(ushr),
+                ("<", true) => cmp!(IntCC::SignedLessThan),
+                ("<", false) => cmp!(IntCC::UnsignedLessThan),
+                ("<=", true) => cmp!(IntCC::SignedLessThanOrEqual),
+                ("<=", false) => cmp!(IntCC::UnsignedLessThanOrEqual),
+                (">", true) => cmp!(IntCC::SignedGreaterThan),
+                (">", false) => cmp!(IntCC::UnsignedGreaterThan),
+                (">=", true) => cmp!(IntCC::SignedGreaterThanOrEqual),
+                (">=", false) => cmp!(IntCC::UnsignedGreaterThanOrEqual),
+                ("==", _) => cmp!(IntCC::Equal),
+                ("!=", _) => cmp!(IntCC::NotEqual),
+                _ => return Err(unsupported()),
+            }
         }
+
+        (Ty::Bool, Ty::Bool) => match op.as_ref() {
+            "==" => cmp!(IntCC::Equal),
+            "!=" => cmp!(IntCC::NotEqual),
+            _ => return Err(unsupported()),
+        },
+
+        _ => return Err(unsupported()),
     };
 
-    match (&**left, &**right, op.as_ref()) {
-        (
-            TypedExpression::Int { value: lval, .. },
-            TypedExpression::Int { value: rval, .. },
-            "+",
-        ) => Ok(compile_infix_iadd(state, *lval, *rval)),
-        (
-            TypedExpression::Int { value: lval, .. },
-            TypedExpression::Int { value: rval, .. },
-            "-",
-        ) => Ok(compile_infix_isub(state, *lval, *rval)),
-        (
-            TypedExpression::Int { value: lval, .. },
-            TypedExpression::Int { value: rval, .. },
-            "*",
-        ) => Ok(compile_infix_imul(state, *lval, *rval)),
-        (
-            TypedExpression::Int { value: lval, .. },
-            TypedExpression::Int { value: rval, .. },
-            "==",
-        ) => Ok(compile_infix_ieq(state, *lval, *rval)),
-        (
-            TypedExpression::Int { value: lval, .. },
-            TypedExpression::Int { value: rval, .. },
-            "!=",
-        ) => Ok(compile_infix_ineq(state, *lval, *rval)),
-        (_, _, op) => non_const_handler(left, right, op),
+    Ok(op_func(state, lval, rval))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::session::{CompileOptions, Session};
+
+    const SOURCE: &str = r#"
+        func sdiv(a: i32, b: i32) -> i32 { a / b }
+        func srem(a: i32, b: i32) -> i32 { a % b }
+        func udiv(a: u32, b: u32) -> u32 { a / b }
+        func urem(a: u32, b: u32) -> u32 { a % b }
+        func sshr(a: i32, b: i32) -> i32 { a >> b }
+        func ushr(a: u32, b: u32) -> u32 { a >> b }
+        func shl(a: u8, b: u8) -> u8 { a << b }
+        func bits(a: u16, b: u16) -> u16 { (a & b) | (a ^ b) }
+        func slt(a: i8, b: i8) -> bool { a < b }
+        func ult(a: u8, b: u8) -> bool { a < b }
+        func sge(a: i64, b: i64) -> bool { a >= b }
+        func ule(a: usize, b: usize) -> bool { a <= b }
+
+        func folded_div() -> u8 { (200u8 / 3u8) + (250u8 % 7u8) }
+        func folded_cmp() -> bool { 200u8 > 100u8 }
+        func folded_bits() -> u32 { (2147483648u32 >> 30u32) | (5u32 ^ 6u32) }
+    "#;
+
+    const LOGICAL_SOURCE: &str = r#"
+        func logical_and(a: bool, b: bool) -> bool { a && b }
+        func logical_or(a: bool, b: bool) -> bool { a || b }
+        func checked_and(n: i32) -> bool { n != 0i32 && 10i32 / n > 1i32 }
+        func checked_or(n: i32) -> bool { n == 0i32 || 10i32 / n > 1i32 }
+    "#;
+
+    #[test]
+    fn signed_and_unsigned_operators() {
+        let program = Session::new(CompileOptions::new()).compile(SOURCE).unwrap();
+
+        let sdiv: extern "C" fn(i32, i32) -> i32 = program.get("sdiv").unwrap();
+        let srem: extern "C" fn(i32, i32) -> i32 = program.get("srem").unwrap();
+        let udiv: extern "C" fn(u32, u32) -> u32 = program.get("udiv").unwrap();
+        let urem: extern "C" fn(u32, u32) -> u32 = program.get("urem").unwrap();
+        assert_eq!(sdiv(-7, 2), -3);
+        assert_eq!(srem(-7, 2), -1);
+        assert_eq!(udiv(u32::MAX, 2), u32::MAX / 2);
+        assert_eq!(urem(u32::MAX, 10), u32::MAX % 10);
+
+        let sshr: extern "C" fn(i32, i32) -> i32 = program.get("sshr").unwrap();
+        let ushr: extern "C" fn(u32, u32) -> u32 = program.get("ushr").unwrap();
+        let shl: extern "C" fn(u8, u8) -> u8 = program.get("shl").unwrap();
+        let bits: extern "C" fn(u16, u16) -> u16 = program.get("bits").unwrap();
+        assert_eq!(sshr(-8, 1), -4);
+        assert_eq!(ushr(0x8000_0000, 31), 1);
+        assert_eq!(shl(0x81, 1), 0x02);
+        assert_eq!(bits(0b1100, 0b1010), 0b1110);
+
+        let slt: extern "C" fn(i8, i8) -> bool = program.get("slt").unwrap();
+        let ult: extern "C" fn(u8, u8) -> bool = program.get("ult").unwrap();
+        let sge: extern "C" fn(i64, i64) -> bool = program.get("sge").unwrap();
+        let ule: extern "C" fn(usize, usize) -> bool = program.get("ule").unwrap();
+        assert!(slt(-1, 1));
+        assert!(!ult(200, 100));
+        assert!(sge(-1, -1) && !sge(-2, -1));
+        assert!(ule(1, usize::MAX));
+
+        // 编译期求值与运行时的结果一致
+        let folded_div: extern "C" fn() -> u8 = program.get("folded_div").unwrap();
+        let folded_cmp: extern "C" fn() -> bool = program.get("folded_cmp").unwrap();
+        let folded_bits: extern "C" fn() -> u32 = program.get("folded_bits").unwrap();
+        assert_eq!(folded_div(), 200 / 3 + 250 % 7);
+        assert!(folded_cmp());
+        assert_eq!(folded_bits(), (2147483648 >> 30) | (5 ^ 6));
+    }
+
+    // `checked_and` 与 `checked_or` 的右侧只在需要时计算, n 为 0 时不会除以零
+    #[test]
+    fn short_circuit_logical_operators() {
+        let program = Session::new(CompileOptions::new()).compile(LOGICAL_SOURCE).unwrap();
+
+        let and: extern "C" fn(bool, bool) -> bool = program.get("logical_and").unwrap();
+        let or: extern "C" fn(bool, bool) -> bool = program.get("logical_or").unwrap();
+
+        for a in [false, true] {
+            for b in [false, true] {
+                assert_eq!(and(a, b), a && b);
+                assert_eq!(or(a, b), a || b);
+            }
+        }
+
+        let checked_and: extern "C" fn(i32) -> bool = program.get("checked_and").unwrap();
+        let checked_or: extern "C" fn(i32) -> bool = program.get("checked_or").unwrap();
+        assert!(!checked_and(0) && checked_and(5) && !checked_and(20));
+        assert!(checked_or(0) && checked_or(5) && !checked_or(20));
     }
 }
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use ant_ast::expr::IntValue;
+use ant_type_checker::{
+    ty::Ty,
+    typed_ast::{GetType, typed_expr::TypedExpression},
+};
+use cranelift::prelude::{InstBuilder, Value, types};
+use cranelift_module::Module;
+
+use crate::{
+    compiler::{
+        Compiler, FunctionState,
+        imm::{int_value_to_imm, int_value_to_int_type},
+    },
+    diagnostic::Diagnostic,
+    tr,
+};
+
+/// 整数的 `-` (按位宽回绕, 无符号整数同样取补码) 与 `~`
+pub fn fold_int_prefix(op: &str, value: IntValue) -> Option<IntValue> {
+    macro_rules! fold {
+        ($($variant:ident),*) => {
+            match value {
+                $(
+                    IntValue::$variant(it) => IntValue::$variant(match op {
+                        "-" => it.wrapping_neg(),
+                        "~" => !it,
+                        _ => return None,
+                    }),
+                )*
+            }
+        };
+    }
+
+    Some(fold!(I8, I16, I32, I64, ISize, U8, U16, U32, U64, USize))
+}
+
+pub fn compile_prefix<M: Module>(
+    state: &mut FunctionState<'_, M>,
+    op: Arc<str>,
+    right: &Box<TypedExpression>,
+) -> Result<Value, Diagnostic> {
+    // 操作数是字面量时在编译期求值, 否则在运行时计算
+    let folded = match (&**right, op.as_ref()) {
+        (TypedExpression::Bool { value, .. }, "!") => Some((types::I8, !value as i64)),
+        (TypedExpression::Int { value, .. }, _) => fold_int_prefix(&op, *value).map(|it| {
+            (
+                int_value_to_int_type(&it, state.target_isa.pointer_type()),
+                int_value_to_imm(&it).bits(),
+            )
+        }),
+        _ => None,
+    };
+
+    if let Some((ty, value)) = folded {
+        return Ok(state.builder.ins().iconst(ty, value));
+    }
+
+    let val = Compiler::compile_expr(state, right)?;
+
+    match (right.get_type(), op.as_ref()) {
+        // bool 只有 0 与 1
+        (Ty::Bool, "!") => Ok(state.builder.ins().bxor_imm(val, 1)),
+        (Ty::IntTy(_), "-") => Ok(state.builder.ins().ineg(val)),
+        (Ty::IntTy(_), "~") => Ok(state.builder.ins().bnot(val)),
+        (ty, _) => Err(Diagnostic::unsupported(tr!(
+            "construct-prefix-operator",
+            op = op,
+            ty = ty
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::session::{CompileOptions, Session};
+
+    const SOURCE: &str = r#"
+        func logical_not(a: bool) -> bool { !a }
+        func neg(a: i32) -> i32 { -a }
+        func neg_u8(a: u8) -> u8 { -a }
+        func inv(a: u16) -> u16 { ~a }
+        func folded() -> i64 { -(~5i64) }
+    "#;
+
+    #[test]
+    fn prefix_operators() {
+        let program = Session::new(CompileOptions::new()).compile(SOURCE).unwrap();
+
+        let not: extern "C" fn(bool) -> bool = program.get("logical_not").unwrap();
+        assert!(not(false) && !not(true));
+
+        let neg: extern "C" fn(i32) -> i32 = program.get("neg").unwrap();
+        let neg_u8: extern "C" fn(u8) -> u8 = program.get("neg_u8").unwrap();
+        let inv: extern "C" fn(u16) -> u16 = program.get("inv").unwrap();
+        let folded: extern "C" fn() -> i64 = program.get("folded").unwrap();
+        assert_eq!(neg(7), -7);
+        assert_eq!(neg(i32::MIN), i32::MIN);
+        assert_eq!(neg_u8(1), u8::MAX);
+        assert_eq!(inv(0x00ff), 0xff00);
+        assert_eq!(folded(), 6);
+    }
+}
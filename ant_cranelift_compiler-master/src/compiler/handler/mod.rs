@@ -1 +1,2 @@
 pub mod compile_infix;
+pub mod compile_prefix;
@@ -0,0 +1,106 @@
+//! `--crate-type=staticlib|cdylib` 时生成的 C 头文件
+
+use std::fmt::Write;
+
+use ant_type_checker::{
+    ty::Ty,
+    typed_ast::{
+        GetType, typed_expr::TypedExpression, typed_node::TypedNode, typed_stmt::TypedStatement,
+    },
+};
+
+use crate::{compiler::convert_type::convert_type_to_c_type, diagnostic::Diagnostic};
+
+/// 为程序中的顶层结构体与函数生成 C 声明
+///
+/// name: 库名, 用于 include guard
+pub fn generate_header(program: &TypedNode, name: &str) -> Result<String, Diagnostic> {
+    let TypedNode::Program { statements, .. } = program;
+
+    let guard = name
+        .chars()
+        .map(|it| {
+            if it.is_ascii_alphanumeric() {
+                it.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    let mut header = String::new();
+
+    writeln!(header, "/* Generated by antc. Do not edit. */").unwrap();
+    writeln!(header, "#ifndef {guard}_H").unwrap();
+    writeln!(header, "#define {guard}_H\n").unwrap();
+    writeln!(header, "#include <stdbool.h>").unwrap();
+    writeln!(header, "#include <stddef.h>").unwrap();
+    writeln!(header, "#include <stdint.h>\n").unwrap();
+    writeln!(header, "#ifdef __cplusplus\nextern \"C\" {{\n#endif\n").unwrap();
+
+    // 1. 结构体: 第一个字段是引用计数, 布局与 compile_struct_layout 一致
+    for stmt in statements {
+        let TypedStatement::Struct { ty, .. } = stmt else {
+            continue;
+        };
+
+        let Ty::Struct { name, fields, .. } = ty else {
+            continue;
+        };
+
+        writeln!(header, "typedef struct {name} {{").unwrap();
+        writeln!(header, "    size_t __ref_count__;").unwrap();
+
+        for (field, field_ty) in fields.iter() {
+            // 结构体字段按值内联存放
+            let c_ty = match field_ty {
+                Ty::Struct { name, .. } => name.to_string(),
+                ty => convert_type_to_c_type(ty)?,
+            };
+
+            writeln!(header, "    {c_ty} {field};").unwrap();
+        }
+
+        writeln!(header, "}} {name};\n").unwrap();
+    }
+
+    // 2. 函数
+    for stmt in statements {
+        let TypedStatement::ExpressionStatement(TypedExpression::Function {
+            name: Some(name),
+            params,
+            block,
+            ..
+        }) = stmt
+        else {
+            continue;
+        };
+
+        let mut c_params = vec![];
+
+        for param in params {
+            if let TypedExpression::TypeHint(param_name, _, ty) = &**param {
+                c_params.push(format!("{} {}", convert_type_to_c_type(ty)?, param_name.value));
+            }
+        }
+
+        let c_params = if c_params.is_empty() {
+            "void".to_string()
+        } else {
+            c_params.join(", ")
+        };
+
+        writeln!(
+            header,
+            "{} {}({c_params});",
+            convert_type_to_c_type(&block.get_type())?,
+            name.value
+        )
+        .unwrap();
+    }
+
+    writeln!(header, "\n#ifdef __cplusplus\n}}\n#endif\n").unwrap();
+    writeln!(header, "#endif /* {guard}_H */").unwrap();
+
+    Ok(header)
+}
@@ -1,8 +1,6 @@
 use ant_ast::expr::IntValue;
 use cranelift::prelude::{Imm64, Uimm64, types};
 
-use crate::compiler::get_platform_width;
-
 pub trait IntoImm {
     type ImmType;
     fn into_imm(self) -> Self::ImmType;
@@ -22,16 +20,10 @@ impl IntoImm for u64 {
     }
 }
 
-pub fn platform_width_to_int_type() -> cranelift::prelude::Type {
-    match get_platform_width() {
-        64 => types::I64,
-        32 => types::I32,
-        16 => types::I16,
-        _ => unreachable!(),
-    }
-}
-
-pub fn int_value_to_int_type(value: &IntValue) -> cranelift::prelude::Type {
+pub fn int_value_to_int_type(
+    value: &IntValue,
+    pointer_type: cranelift::prelude::Type,
+) -> cranelift::prelude::Type {
     match value {
         IntValue::I64(_it) => types::I64,
         IntValue::I32(_it) => types::I32,
@@ -41,8 +33,8 @@ pub fn int_value_to_int_type(value: &IntValue) -> cranelift::prelude::Type {
         IntValue::U32(_it) => types::I32,
         IntValue::U16(_it) => types::I16,
         IntValue::U8(_it) => types::I8,
-        IntValue::ISize(_it) => platform_width_to_int_type(),
-        IntValue::USize(_it) => platform_width_to_int_type(),
+        IntValue::ISize(_it) => pointer_type,
+        IntValue::USize(_it) => pointer_type,
     }
 }
 
@@ -0,0 +1,559 @@
+use std::{
+    cell::RefCell,
+    ffi::{CString, c_char},
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use ant_type_checker::{
+    table::TypeTable,
+    ty::{IntTy, Ty},
+    typed_ast::{
+        GetType, typed_expr::TypedExpression, typed_node::TypedNode,
+        typed_stmt::TypedStatement,
+    },
+};
+use cranelift::prelude::{AbiParam, InstBuilder, MemFlags, Value, types};
+use cranelift_codegen::{
+    ir::{Function, Signature, UserFuncName},
+    isa::TargetIsa,
+};
+use cranelift_frontend::FunctionBuilder;
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{
+    DataDescription, FuncId, FuncOrDataId, Linkage, Module, default_libcall_names,
+};
+
+use crate::{
+    compiler::{
+        Compiler, FunctionState, GlobalState, create_target_isa, host_triple, runtime,
+        table::SymbolTable,
+    },
+    diagnostic::Diagnostic,
+    session::{CompileOptions, CrateType},
+    tr,
+};
+
+/// JIT 使用的 ISA: 代码直接放在本进程的内存中, cranelift-jit 不支持位置无关代码
+pub fn create_jit_isa(options: &CompileOptions) -> Result<Arc<dyn TargetIsa>, Diagnostic> {
+    // 生成的代码在本进程中执行, 只能为本机编译
+    if let Some(target) = options.target.as_ref().filter(|it| **it != host_triple()) {
+        return Err(Diagnostic::error(tr!("jit-cross-target", target = target)));
+    }
+
+    create_target_isa(&options.clone().crate_type(CrateType::Bin).pie(Some(false)))
+}
+
+impl Compiler<JITModule> {
+    /// 创建 JIT 编译器
+    ///
+    /// `__obj_*` 等运行时函数解析到链接进 antc 的运行时库 (`runtime/*.c`)
+    ///
+    /// `extern "C"` 函数按名字在当前进程中查找
+    ///
+    /// `options.host_functions` 中的函数优先于进程中的同名符号
+    pub fn new_jit(
+        target_isa: Arc<dyn TargetIsa>,
+        table: Rc<RefCell<SymbolTable>>,
+        type_table: Arc<Mutex<TypeTable>>,
+        options: CompileOptions,
+    ) -> Result<Compiler<JITModule>, Diagnostic> {
+        let mut builder = JITBuilder::with_isa(target_isa.clone(), default_libcall_names());
+
+        for (name, ptr) in runtime::jit_symbols() {
+            builder.symbol(name, ptr);
+        }
+
+        for (name, ptr) in &options.host_functions {
+            builder.symbol(name, *ptr);
+        }
+
+        Self::from_module(JITModule::new(builder), target_isa, table, type_table, options)
+    }
+
+    /// 编译整个程序并完成重定位, 返回可以直接调用的函数
+    pub fn compile_jit(mut self, program: TypedNode) -> Result<JitProgram, Vec<Diagnostic>> {
+        self.codegen(program)?;
+
+        self.module
+            .finalize_definitions()
+            .map_err(|it| vec![Diagnostic::error(tr!("jit-finalize-failed", error = it))])?;
+
+        Ok(JitProgram {
+            module: self.module,
+        })
+    }
+}
+
+/// REPL 中一条输入编译出的函数, 返回值已扩展为 i64
+pub struct ReplEntry {
+    pub func_id: FuncId,
+    pub ty: Ty,
+}
+
+impl Compiler<JITModule> {
+    /// 把一条 REPL 输入编译为无参函数 `name` 并完成重定位
+    ///
+    /// 函数和常量定义到模块中, 顶层 `let` 保存到全局数据对象, 二者对之后的输入都可见
+    pub fn compile_repl_entry(
+        &mut self,
+        name: &str,
+        statements: Vec<TypedStatement>,
+    ) -> Result<ReplEntry, Vec<Diagnostic>> {
+        let (top_level, statements): (Vec<_>, Vec<_>) =
+            statements.into_iter().partition(Self::is_top_level_stmt);
+
+        let mut state = GlobalState {
+            target_isa: self.target_isa.clone(),
+            module: &mut self.module,
+            function_map: &mut self.function_map,
+            data_map: &mut self.data_map,
+
+            table: self.table.clone(),
+            type_table: self.type_table.clone(),
+
+            arc_alloc: self.arc_alloc,
+            arc_retain: self.arc_retain,
+            arc_release: self.arc_release,
+
+            diagnostics: &mut self.diagnostics,
+            dump: &mut self.dump,
+            str_counter: &mut self.str_counter,
+            cache: self.cache.as_mut(),
+            pending: None,
+        };
+
+        for stmt in &top_level {
+            if let Err(err) = Self::compile_top_level_stmt(&mut state, stmt) {
+                state.diagnostics.push(err);
+            }
+        }
+
+        let ty = match statements.last() {
+            Some(TypedStatement::ExpressionStatement(expr)) => expr.get_type(),
+            _ => Ty::Unit,
+        };
+
+        let func_id = self.compile_repl_function(name, &statements, &ty);
+
+        if !self.diagnostics.is_empty() {
+            self.module.clear_context(&mut self.context);
+            return Err(std::mem::take(&mut self.diagnostics).into_vec());
+        }
+
+        let func_id = func_id.map_err(|it| vec![it])?;
+
+        self.module
+            .finalize_definitions()
+            .map_err(|it| vec![Diagnostic::error(tr!("jit-finalize-failed", error = it))])?;
+
+        Ok(ReplEntry { func_id, ty })
+    }
+
+    fn compile_repl_function(
+        &mut self,
+        name: &str,
+        statements: &[TypedStatement],
+        ty: &Ty,
+    ) -> Result<FuncId, Diagnostic> {
+        let mut sig = Signature::new(self.target_isa.default_call_conv());
+        sig.returns.push(AbiParam::new(types::I64));
+
+        let func_id = self
+            .module
+            .declare_function(name, Linkage::Local, &sig)
+            .map_err(|e| tr!("declare-function-failed", name = name, error = e))?;
+
+        self.context.func = Function::with_name_signature(UserFuncName::user(0, 0), sig);
+
+        let mut builder = FunctionBuilder::new(&mut self.context.func, &mut self.builder_ctx);
+
+        let entry = builder.create_block();
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+
+        let mut ret_val = builder.ins().iconst(types::I64, 0);
+
+        let mut state = FunctionState {
+            builder,
+            target_isa: self.target_isa.clone(),
+            module: &mut self.module,
+            function_map: &mut self.function_map,
+            data_map: &mut self.data_map,
+
+            table: self.table.clone(),
+            type_table: self.type_table.clone(),
+
+            arc_alloc: self.arc_alloc,
+            arc_retain: self.arc_retain,
+            arc_release: self.arc_release,
+
+            diagnostics: &mut self.diagnostics,
+            dump: &mut self.dump,
+            str_counter: &mut self.str_counter,
+        };
+
+        for stmt in statements {
+            let result = match stmt {
+                TypedStatement::Let { name, value, ty, .. } => {
+                    Self::compile_repl_let(&mut state, &name.value, value, ty)
+                }
+                stmt => Self::compile_stmt(&mut state, stmt),
+            };
+
+            ret_val = match result {
+                Ok(val) => val,
+                Err(err) => state.recover(err),
+            };
+        }
+
+        if !state.diagnostics.is_empty() {
+            return Ok(func_id);
+        }
+
+        // 统一扩展为 i64 返回, 由调用方按类型解释
+        let ret_val = match ty {
+            Ty::Unit => state.builder.ins().iconst(types::I64, 0),
+            ty => {
+                let val_ty = state.builder.func.dfg.value_type(ret_val);
+
+                if val_ty.bits() >= 64 {
+                    ret_val
+                } else if matches!(
+                    ty,
+                    Ty::IntTy(IntTy::I8 | IntTy::I16 | IntTy::I32 | IntTy::I64 | IntTy::ISize)
+                ) {
+                    state.builder.ins().sextend(types::I64, ret_val)
+                } else {
+                    state.builder.ins().uextend(types::I64, ret_val)
+                }
+            }
+        };
+
+        state.builder.ins().return_(&[ret_val]);
+        state.builder.finalize();
+
+        Self::define_function(
+            &mut self.module,
+            &mut self.dump,
+            name,
+            func_id,
+            &mut self.context,
+        )?;
+
+        Ok(func_id)
+    }
+
+    /// 顶层 `let`: 值保存到一个可写的全局数据对象中, 之后的输入通过它读取
+    fn compile_repl_let(
+        state: &mut FunctionState<'_, JITModule>,
+        name: &str,
+        value: &TypedExpression,
+        ty: &Ty,
+    ) -> Result<Value, Diagnostic> {
+        let val = Self::compile_expr(state, value)?;
+
+        // ARC: retain 新值
+        state.retain_if_needed(val, ty);
+
+        let data_name = format!("__repl_{name}_{}", state.data_map.len());
+
+        let data_id = state
+            .module
+            .declare_data(&data_name, Linkage::Local, true, false)
+            .map_err(|e| tr!("declare-variable-failed", name = name, error = e))?;
+
+        let mut data_desc = DataDescription::new();
+        data_desc.define_zeroinit(8);
+
+        state
+            .module
+            .define_data(data_id, &data_desc)
+            .map_err(|e| tr!("declare-variable-failed", name = name, error = e))?;
+
+        state.data_map.insert(name.to_string(), data_id);
+
+        let global_var = state
+            .module
+            .declare_data_in_func(data_id, state.builder.func);
+        let val_ptr = state
+            .builder
+            .ins()
+            .global_value(state.target_isa.pointer_type(), global_var);
+
+        state.builder.ins().store(MemFlags::new(), val, val_ptr, 0);
+
+        state.table.borrow_mut().define_static(name);
+
+        Ok(state.builder.ins().iconst(types::I64, 0)) // unit
+    }
+
+    pub fn get_finalized_function(&self, func_id: FuncId) -> *const u8 {
+        self.module.get_finalized_function(func_id)
+    }
+}
+
+/// JIT 编译完成的程序, 机器码在它被丢弃前一直有效
+pub struct JitProgram {
+    module: JITModule,
+}
+
+#[allow(unused)]
+impl JitProgram {
+    /// 本模块中已定义 (而非导入) 的函数
+    pub fn get_func_id(&self, name: &str) -> Option<FuncId> {
+        let FuncOrDataId::Func(func_id) = self.module.get_name(name)? else {
+            return None;
+        };
+
+        self.module
+            .declarations()
+            .get_function_decl(func_id)
+            .linkage
+            .is_definable()
+            .then_some(func_id)
+    }
+
+    pub fn signature(&self, func_id: FuncId) -> &Signature {
+        &self.module.declarations().get_function_decl(func_id).signature
+    }
+
+    /// 已定义函数的入口地址
+    pub fn get_function(&self, name: &str) -> Option<*const u8> {
+        self.get_func_id(name)
+            .map(|it| self.module.get_finalized_function(it))
+    }
+
+    /// 按名字取出 Ant 函数, 并检查它与 Rust 函数类型 `F` 的签名一致
+    ///
+    /// ```ignore
+    /// let add: extern "C" fn(i64, i64) -> i64 = program.get("add")?;
+    /// assert_eq!(add(1, 2), 3);
+    /// ```
+    pub fn get<F: JitFunction>(&self, name: &str) -> Result<F, Diagnostic> {
+        let func_id = self
+            .get_func_id(name)
+            .ok_or_else(|| Diagnostic::error(tr!("function-not-found", name = name)))?;
+
+        let signature = self.signature(func_id);
+        let (params, returns) = value_types(signature);
+
+        if params != F::params() || returns != F::returns() {
+            return Err(Diagnostic::error(tr!(
+                "function-signature-mismatch",
+                name = name,
+                expected = signature
+            )));
+        }
+
+        // SAFETY: 参数与返回值类型已与 Cranelift 签名逐一比对
+        Ok(unsafe { F::from_ptr(self.module.get_finalized_function(func_id)) })
+    }
+
+    /// 调用 `main`, 支持 `main()` 与 `main(argc, argv)` 两种形式, 返回退出码
+    pub fn run_main(&self, args: &[String]) -> Result<i32, Diagnostic> {
+        let func_id = self
+            .get_func_id("main")
+            .ok_or_else(|| Diagnostic::error(tr!("main-not-found")))?;
+
+        let main = self.module.get_finalized_function(func_id);
+        let signature = self.signature(func_id);
+
+        let argv_owned = args
+            .iter()
+            .map(|it| CString::new(it.as_str()).unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        let mut argv = argv_owned
+            .iter()
+            .map(|it| it.as_ptr())
+            .collect::<Vec<_>>();
+        argv.push(std::ptr::null());
+
+        let (params, returns) = value_types(signature);
+
+        // SAFETY: 函数由本模块生成且已完成重定位, 参数与返回值类型在下面逐一比对
+        let code = unsafe {
+            match (params.as_slice(), returns.as_slice()) {
+                ([], []) => {
+                    let main: extern "C" fn() = std::mem::transmute(main);
+                    main();
+                    0
+                }
+                ([], [ret]) if *ret == types::I32 => {
+                    let main: extern "C" fn() -> i32 = std::mem::transmute(main);
+                    main()
+                }
+                ([argc_ty, argv_ty], [ret])
+                    if *argc_ty == types::I32
+                        && *argv_ty == host_pointer_type()
+                        && *ret == types::I32 =>
+                {
+                    let main: extern "C" fn(i32, *const *const c_char) -> i32 =
+                        std::mem::transmute(main);
+                    main(args.len() as i32, argv.as_ptr())
+                }
+                _ => {
+                    return Err(Diagnostic::error(tr!(
+                        "main-bad-signature",
+                        signature = signature
+                    )));
+                }
+            }
+        };
+
+        Ok(code)
+    }
+}
+
+/// 签名中参数与返回值的 Cranelift 类型
+fn value_types(signature: &Signature) -> (Vec<types::Type>, Vec<types::Type>) {
+    let params = signature.params.iter().map(|it| it.value_type).collect();
+    let returns = signature.returns.iter().map(|it| it.value_type).collect();
+
+    (params, returns)
+}
+
+/// JIT 代码运行在当前进程中, 指针宽度即宿主的指针宽度
+fn host_pointer_type() -> types::Type {
+    match usize::BITS {
+        32 => types::I32,
+        _ => types::I64,
+    }
+}
+
+/// 可以出现在 Ant 函数签名中的 Rust 类型
+pub trait AbiType {
+    fn abi_type() -> types::Type;
+}
+
+macro_rules! impl_abi_type {
+    ($($ty:ty => $abi:expr),+ $(,)?) => {
+        $(
+            impl AbiType for $ty {
+                fn abi_type() -> types::Type {
+                    $abi
+                }
+            }
+        )+
+    };
+}
+
+impl_abi_type! {
+    i8 => types::I8,
+    u8 => types::I8,
+    bool => types::I8,
+    i16 => types::I16,
+    u16 => types::I16,
+    i32 => types::I32,
+    u32 => types::I32,
+    i64 => types::I64,
+    u64 => types::I64,
+    isize => host_pointer_type(),
+    usize => host_pointer_type(),
+}
+
+impl<T> AbiType for *const T {
+    fn abi_type() -> types::Type {
+        host_pointer_type()
+    }
+}
+
+impl<T> AbiType for *mut T {
+    fn abi_type() -> types::Type {
+        host_pointer_type()
+    }
+}
+
+/// 返回值类型, `()` 对应没有返回值
+pub trait AbiReturn {
+    fn abi_returns() -> Vec<types::Type>;
+}
+
+impl AbiReturn for () {
+    fn abi_returns() -> Vec<types::Type> {
+        vec![]
+    }
+}
+
+impl<T: AbiType> AbiReturn for T {
+    fn abi_returns() -> Vec<types::Type> {
+        vec![T::abi_type()]
+    }
+}
+
+/// 可以与 JIT 函数互相转换的 `extern "C" fn`
+pub trait JitFunction: Copy {
+    fn params() -> Vec<types::Type>;
+    fn returns() -> Vec<types::Type>;
+    fn as_ptr(self) -> *const u8;
+
+    /// # Safety
+    ///
+    /// `ptr` 必须指向签名与 `Self` 一致的函数
+    unsafe fn from_ptr(ptr: *const u8) -> Self;
+}
+
+macro_rules! impl_jit_function {
+    ($($arg:ident),*) => {
+        impl<$($arg: AbiType,)* R: AbiReturn> JitFunction for extern "C" fn($($arg),*) -> R {
+            fn params() -> Vec<types::Type> {
+                vec![$($arg::abi_type()),*]
+            }
+
+            fn returns() -> Vec<types::Type> {
+                R::abi_returns()
+            }
+
+            fn as_ptr(self) -> *const u8 {
+                self as *const u8
+            }
+
+            unsafe fn from_ptr(ptr: *const u8) -> Self {
+                unsafe { std::mem::transmute_copy(&ptr) }
+            }
+        }
+    };
+}
+
+impl_jit_function!();
+impl_jit_function!(A);
+impl_jit_function!(A, B);
+impl_jit_function!(A, B, C);
+impl_jit_function!(A, B, C, D);
+impl_jit_function!(A, B, C, D, E);
+impl_jit_function!(A, B, C, D, E, F);
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        compiler::{host_triple, jit::create_jit_isa},
+        session::{CompileOptions, Session},
+    };
+
+    #[test]
+    fn jit_rejects_cross_target() {
+        let options =
+            CompileOptions::new().target(Some("riscv64gc-unknown-linux-gnu".to_string()));
+        assert!(create_jit_isa(&options).is_err());
+
+        let options = CompileOptions::new().target(Some(host_triple()));
+        assert!(create_jit_isa(&options).is_ok());
+    }
+
+    #[test]
+    fn run_main_checks_value_types() {
+        let run = |source: &str| {
+            Session::new(CompileOptions::new())
+                .compile(source)
+                .unwrap()
+                .run_main(&["main".to_string()])
+        };
+
+        assert_eq!(run("func main() -> i32 {\n    3i32\n}").unwrap(), 3);
+
+        // 参数个数相同但类型不同的 `main` 不能按 `main()` 或 `main(argc, argv)` 调用
+        assert!(run("func main() -> bool {\n    true\n}").is_err());
+        assert!(run("func main(a: i64, b: i64) -> i64 {\n    a + b\n}").is_err());
+    }
+}
@@ -1,36 +1,45 @@
 pub mod arc;
+pub mod cache;
 pub mod compile_state_impl;
 pub mod compiler_impl;
 pub mod handler;
+pub mod header;
+pub mod jit;
+pub mod parallel;
 pub mod table;
 
-mod constants;
 mod convert_type;
 mod imm;
+mod runtime;
 
 
 use std::cell::RefCell;
-use std::env::{current_dir, current_exe};
 use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
 use std::sync::Mutex;
 use std::{collections::HashMap, fs, path::Path, rc::Rc, sync::Arc};
 
 use ant_type_checker::table::TypeTable;
 use cranelift_codegen::{
-    isa::TargetIsa,
-    settings::{self, Configurable},
+    isa::{self, TargetIsa},
+    settings::{self, Configurable, SetError},
 };
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
-use cranelift_module::FuncId;
+use cranelift_module::{FuncId, Module};
 use cranelift_object::ObjectModule;
+use target_lexicon::{OperatingSystem, Triple};
 
+use crate::compiler::cache::FunctionCache;
+use crate::compiler::parallel::PendingFunction;
 use crate::compiler::table::SymbolTable;
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::session::{CompileOptions, CrateType, LinkMode, TargetCpu};
+use crate::tr;
 
-use crate::args::read_arg;
-
-// 编译器结构体
-pub struct Compiler {
-    module: ObjectModule,
+// 编译器结构体, 对 `cranelift_module::Module` 泛型: AOT 使用 ObjectModule, `antc run` 使用 JITModule
+pub struct Compiler<M: Module = ObjectModule> {
+    module: M,
 
     builder_ctx: FunctionBuilderContext,
     context: cranelift_codegen::Context,
@@ -46,11 +55,53 @@ pub struct Compiler {
     arc_alloc: FuncId,
     arc_retain: FuncId,
     arc_release: FuncId,
+
+    diagnostics: Diagnostics,
+    dump: CodeDump,
+
+    options: CompileOptions,
+    /// 生成的函数会被其他目标文件调用, 不删除 `main` 用不到的函数
+    library: bool,
+    /// 本次编译中已生成的字符串字面量个数, 用于命名数据对象
+    str_counter: usize,
+    /// 增量编译缓存, 未启用时为 None
+    cache: Option<FunctionCache>,
+}
+
+/// 一个函数的 Cranelift IR 与最终机器码的反汇编
+#[derive(Debug, Clone, Default)]
+pub struct FunctionDump {
+    pub name: String,
+    pub clif: Option<String>,
+    pub asm: Option<String>,
+}
+
+/// 按需收集每个函数的 IR 与反汇编, 供 `--emit=clif|asm` 使用
+#[derive(Debug, Clone, Default)]
+pub struct CodeDump {
+    pub clif: bool,
+    pub asm: bool,
+    pub functions: Vec<FunctionDump>,
+}
+
+impl CodeDump {
+    pub fn is_enabled(&self) -> bool {
+        self.clif || self.asm
+    }
+}
+
+/// 一次编译的产物
+#[derive(Debug, Clone)]
+pub struct CompiledProgram {
+    pub object: Vec<u8>,
+    pub functions: Vec<FunctionDump>,
+    /// 使用了增量编译缓存的函数
+    pub cached: Vec<String>,
 }
 
-pub struct GlobalState<'a> {
+pub struct GlobalState<'a, M: Module> {
     pub target_isa: Arc<dyn TargetIsa>,
-    pub module: &'a mut ObjectModule,
+    pub module: &'a mut M,
     pub function_map: &'a mut HashMap<String, cranelift_module::FuncId>,
     pub data_map: &'a mut HashMap<String, cranelift_module::DataId>,
     
@@ -60,12 +111,19 @@ pub struct GlobalState<'a> {
     pub arc_alloc: FuncId,
     pub arc_retain: FuncId,
     pub arc_release: FuncId,
+
+    pub diagnostics: &'a mut Diagnostics,
+    pub dump: &'a mut CodeDump,
+    pub str_counter: &'a mut usize,
+    pub cache: Option<&'a mut FunctionCache>,
+    /// 并行编译时收集生成好 IR 的顶层函数, None 表示立即编译并定义
+    pub pending: Option<Vec<PendingFunction>>,
 }
 
-pub struct FunctionState<'a> {
+pub struct FunctionState<'a, M: Module> {
     pub builder: FunctionBuilder<'a>,
     pub target_isa: Arc<dyn TargetIsa>,
-    pub module: &'a mut ObjectModule,
+    pub module: &'a mut M,
     pub function_map: &'a mut HashMap<String, cranelift_module::FuncId>,
     pub data_map: &'a mut HashMap<String, cranelift_module::DataId>,
     
@@ -75,12 +133,18 @@ pub struct FunctionState<'a> {
     pub arc_alloc: FuncId,
     pub arc_retain: FuncId,
     pub arc_release: FuncId,
+
+    pub diagnostics: &'a mut Diagnostics,
+    pub dump: &'a mut CodeDump,
+    pub str_counter: &'a mut usize,
 }
 
 #[allow(unused)]
 pub trait CompileState {
+    type Module: Module;
+
     fn get_target_isa(&self) -> Arc<dyn TargetIsa>;
-    fn get_module(&mut self) -> &mut ObjectModule;
+    fn get_module(&mut self) -> &mut Self::Module;
     fn get_function_map(&mut self) -> &mut HashMap<String, cranelift_module::FuncId>;
     fn get_data_map(&mut self) -> &mut HashMap<String, cranelift_module::DataId>;
     
@@ -90,149 +154,329 @@ pub trait CompileState {
     fn get_arc_alloc(&self) -> FuncId;
     fn get_arc_retain(&self) -> FuncId;
     fn get_arc_release(&self) -> FuncId;
+
+    fn get_diagnostics(&mut self) -> &mut Diagnostics;
 }
 
 // 创建目标 ISA 的辅助函数
-pub fn create_target_isa() -> Arc<dyn TargetIsa> {
+//
+// 未指定 `options.target` 时使用本机 ISA, 未指定 `options.target_cpu` 时
+// 本机编译启用当前 CPU 支持的所有特性, 交叉编译只使用目标平台的基础特性
+pub fn create_target_isa(options: &CompileOptions) -> Result<Arc<dyn TargetIsa>, Diagnostic> {
     let mut flag_builder = settings::builder();
-    flag_builder.set("opt_level", "speed").unwrap();
+    flag_builder
+        .set("opt_level", options.opt_level.cranelift_opt_level())
+        .unwrap();
+    flag_builder
+        .set("enable_verifier", &options.opt_level.enable_verifier().to_string())
+        .unwrap();
+
+    let mut isa_builder = match (&options.target, options.target_cpu) {
+        (Some(target), Some(TargetCpu::Native)) => {
+            return Err(tr!("target-cpu-native-cross", target = target).into());
+        }
+        (Some(target), _) => {
+            let triple = Triple::from_str(target)
+                .map_err(|e| tr!("invalid-target", target = target, error = e))?;
+
+            isa::lookup(triple).map_err(|e| tr!("unsupported-target", target = target, error = e))?
+        }
+        (None, None | Some(TargetCpu::Native)) => cranelift_native::builder()
+            .map_err(|e| tr!("host-isa-unavailable", error = e))?,
+        (None, Some(_)) => isa::lookup(Triple::host())
+            .map_err(|e| tr!("host-isa-unavailable", error = e))?,
+    };
+
+    // 库与 PIE 需要位置无关代码: 数据与外部函数的地址通过 GOT 或 PC 相对寻址得到, 不产生文本重定位
+    flag_builder
+        .set("is_pic", &is_pic(options, isa_builder.triple().operating_system).to_string())
+        .unwrap();
+
+    if let Some(preset) = options.target_cpu.and_then(TargetCpu::preset) {
+        isa_builder.enable(preset).map_err(|e| {
+            tr!(
+                "unsupported-target-cpu",
+                cpu = preset,
+                target = isa_builder.triple(),
+                error = e
+            )
+        })?;
+    }
+
+    // `-C key=value`: 先尝试通用设置, 名字不存在时再尝试目标平台专有的设置
+    for (key, value) in &options.codegen_flags {
+        let result = match flag_builder.set(key, value) {
+            Err(SetError::BadName(_)) => isa_builder.set(key, value),
+            result => result,
+        };
+
+        result.map_err(|e| tr!("invalid-codegen-flag", key = key, value = value, error = e))?;
+    }
 
-    let isa_builder = cranelift_native::builder().unwrap();
     isa_builder
         .finish(settings::Flags::new(flag_builder))
-        .unwrap()
+        .map_err(|e| tr!("unsupported-target", target = isa_builder.triple(), error = e).into())
+}
+
+/// 目标平台与全部 Cranelift 设置, 相同时生成的机器码也相同
+pub fn isa_fingerprint(isa: &dyn TargetIsa) -> String {
+    let isa_flags = isa
+        .isa_flags()
+        .iter()
+        .map(|it| it.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!("{}\n{}\n{isa_flags}", isa.triple(), isa.flags())
 }
 
-/// 将对象代码编译为可执行文件
+/// 将一个或多个目标文件 (如分别编译的各个模块) 链接为可执行文件
 ///
 /// output_path: 目录 + 文件名 + 后缀  
+/// options.save_temps: 保留目标文件 `<stem>.o`, 放在输出文件旁边
 pub fn compile_to_executable(
+    objects: &[&[u8]],
+    output_path: &Path,
+    options: &CompileOptions,
+) -> Result<(), Diagnostic> {
+    link(objects, output_path, options, false)
+}
+
+/// 按 `options.crate_type` 将对象代码打包为静态库或链接为动态库
+///
+/// 运行时会一并放进库中, 使用者只需链接这一个库
+pub fn compile_to_library(
     object_code: &[u8],
     output_path: &Path,
-) -> Result<(), Box<dyn std::error::Error>> {
-    use cc;
-    use tempfile;
+    options: &CompileOptions,
+) -> Result<(), Diagnostic> {
+    match options.crate_type {
+        CrateType::Staticlib => archive(object_code, output_path, options),
+        _ => link(&[object_code], output_path, options, true),
+    }
+}
 
-    // 临时 .o
-    let temp_dir = tempfile::tempdir()?;
-    let object_file_path = temp_dir.path().join("output.o");
-    fs::write(&object_file_path, object_code)?;
+/// 未指定 `-o` 时库的文件名, 按目标平台的习惯命名
+pub fn library_file_name(stem: &str, options: &CompileOptions) -> Result<String, Diagnostic> {
+    let os = target_os(options.target.as_deref().unwrap_or(&host_triple()))?;
 
-    // -------- target triple --------
-    #[cfg(target_os = "windows")]
-    let target = "x86_64-pc-windows-gnu";
+    Ok(match (options.crate_type, os) {
+        (CrateType::Cdylib, OperatingSystem::Windows) => format!("{stem}.dll"),
+        (CrateType::Cdylib, os) if is_macos(os) => format!("lib{stem}.dylib"),
+        (CrateType::Cdylib, _) => format!("lib{stem}.so"),
+        _ => format!("lib{stem}.a"),
+    })
+}
+
+/// 是否生成位置无关代码: 库总是需要; 可执行文件默认为 PIE, Windows 除外 (PE 依靠基址重定位)
+fn is_pic(options: &CompileOptions, os: OperatingSystem) -> bool {
+    options.crate_type.is_library() || is_pie(options, os)
+}
+
+fn is_pie(options: &CompileOptions, os: OperatingSystem) -> bool {
+    os != OperatingSystem::Windows && options.pie.unwrap_or(true)
+}
+
+/// antc 所在的平台, 与嵌入的运行时的平台一致
+pub(crate) fn host_triple() -> String {
+    Triple::host().to_string()
+}
+
+fn target_os(target: &str) -> Result<OperatingSystem, Diagnostic> {
+    Ok(Triple::from_str(target)
+        .map_err(|e| tr!("invalid-target", target = target, error = e))?
+        .operating_system)
+}
 
-    #[cfg(target_os = "linux")]
-    let target = "x86_64-unknown-linux-gnu";
+fn is_macos(os: OperatingSystem) -> bool {
+    matches!(os, OperatingSystem::Darwin | OperatingSystem::MacOSX { .. })
+}
 
-    #[cfg(target_os = "macos")]
-    let target = if cfg!(target_arch = "aarch64") {
-        "aarch64-apple-darwin"
+/// 写出目标文件, save_temps 时放在输出文件旁边, 否则放在临时目录中
+///
+/// 有多个目标文件时依次命名为 `<stem>.0.o`、`<stem>.1.o`...
+fn write_objects(
+    temp_dir: &Path,
+    objects: &[&[u8]],
+    output_path: &Path,
+    options: &CompileOptions,
+) -> Result<Vec<PathBuf>, Diagnostic> {
+    let base = if options.save_temps {
+        output_path.to_path_buf()
     } else {
-        "x86_64-apple-darwin"
+        temp_dir.join("output")
     };
 
-    // -------- 先用 cc 生成 libxxx.a --------
-    let mut build = cc::Build::new();
-    build
-        .object(&object_file_path)
-        .opt_level(2)
-        .target(target)
-        .host("CONSOLE")
-        .out_dir(output_path.parent().unwrap_or(Path::new("")));
+    let mut paths = Vec::with_capacity(objects.len());
 
-    build.try_compile(output_path.file_stem().unwrap().to_str().unwrap())?;
+    for (i, object_code) in objects.iter().enumerate() {
+        let object_file_path = if objects.len() == 1 {
+            base.with_extension("o")
+        } else {
+            base.with_extension(format!("{i}.o"))
+        };
 
-    let compiler = build.get_compiler();
+        fs::write(&object_file_path, object_code).map_err(|e| {
+            tr!("write-output-failed", path = object_file_path.display(), error = e)
+        })?;
 
-    let lib_name = format!(
-        "lib{}.a",
-        output_path.file_stem().unwrap().to_str().unwrap()
-    );
-    let lib_path = output_path.parent().unwrap().join(&lib_name);
+        paths.push(object_file_path);
+    }
 
-    // -------- 最终链接 --------
-    let compiler_dir = if std::env::var("CARGO").is_ok() {
-        current_dir().map_or(".".into(), |p| p.display().to_string())
-    } else {
-        current_exe().map_or(".".into(), |p| {
-            p.parent().map_or(".".into(), |it| it.display().to_string())
-        })
+    Ok(paths)
+}
+
+/// 链接可执行文件 (shared 为 false) 或动态库 (shared 为 true)
+fn link(
+    objects: &[&[u8]],
+    output_path: &Path,
+    options: &CompileOptions,
+    shared: bool,
+) -> Result<(), Diagnostic> {
+    // 临时 .o
+    let temp_dir = tempfile::tempdir().map_err(|e| tr!("create-temp-dir-failed", error = e))?;
+    let object_file_paths = write_objects(temp_dir.path(), objects, output_path, options)?;
+
+    // -------- target triple --------
+    let host = host_triple();
+    let target = options.target.as_deref().unwrap_or(&host);
+    let os = target_os(target)?;
+
+    // 动态库只能动态链接
+    let link_mode = match options.link_mode {
+        _ if shared => LinkMode::Dynamic,
+        Some(it) => it,
+        None if os == OperatingSystem::Windows => LinkMode::Static,
+        None => LinkMode::Dynamic,
     };
 
-    let mut command = compiler.to_command();
-    command
-        .arg("-o")
-        .arg(output_path)
-        .arg(&lib_path)
-        .arg("-L")
-        .arg(format!("{}/include", compiler_dir))
-        .arg("-l")
-        .arg("arc");
-
-    // 用户额外链接库
-    if let Some(it) = read_arg() {
-        for path in &it.link_with {
-            command.arg("-L").arg(
-                PathBuf::from(path)
-                    .parent()
-                    .map_or("./".to_string(), |p| p.to_string_lossy().to_string()),
-            );
+    let mut command = linker_command(options, target, &host)?;
+
+    if shared {
+        command.arg("-shared");
+    } else if is_macos(os) {
+        // macOS 上可执行文件默认就是 PIE
+        if !is_pie(options, os) {
+            command.arg("-Wl,-no_pie");
         }
+    } else if os != OperatingSystem::Windows {
+        command.arg(match (is_pie(options, os), link_mode) {
+            (true, LinkMode::Static) => "-static-pie",
+            (true, LinkMode::Dynamic) => "-pie",
+            (false, _) => "-no-pie",
+        });
+    }
 
-        for lib in it.link_with {
-            if lib.trim().is_empty() {
-                continue;
-            }
+    // 目标文件在前, 库在后, 保证链接器能解析目标文件中的符号
+    command.arg("-o").arg(output_path).args(&object_file_paths);
 
-            let stem = PathBuf::from(&lib)
-                .file_stem()
-                .ok_or("invalid lib name")?
-                .to_string_lossy()
-                .to_string();
+    for file in &options.link_with {
+        command.arg(file);
+    }
 
-            let name = stem.strip_prefix("lib").unwrap_or(&stem);
-            command.arg(format!("-l{name}"));
-        }
+    for dir in &options.lib_paths {
+        command.arg("-L").arg(dir);
     }
 
-    // Linux: 静态 + libc + noexecstack
-    #[cfg(target_os = "linux")]
-    {
-        command.arg("-static").arg("-lc").arg("-Wl,-z,noexecstack");
+    // 运行时 (ARC 等)
+    command.arg(runtime::write_runtime(temp_dir.path(), options.target.as_deref(), &host)?);
+
+    for lib in &options.libs {
+        command.arg(format!("-l{lib}"));
     }
 
-    // Windows
-    #[cfg(target_os = "windows")]
-    {
-        command.arg("-static").arg("-lmsvcrt");
+    // `-static-pie` 已包含静态链接
+    if link_mode == LinkMode::Static && !(shared || is_pie(options, os)) {
+        command.arg("-static");
     }
 
-    // macOS: 不要 static / 不要 -lc（clang 自动处理）
+    match os {
+        OperatingSystem::Linux => {
+            command.arg("-Wl,-z,noexecstack");
+        }
+        OperatingSystem::Windows if link_mode == LinkMode::Static => {
+            command.arg("-lmsvcrt");
+        }
+        _ => {}
+    }
 
-    command.status().expect("link failed");
+    command.args(&options.link_args);
+
+    run_link_command(command)
+}
+
+/// 以运行时库为基础追加目标文件, 得到静态库
+fn archive(
+    object_code: &[u8],
+    output_path: &Path,
+    options: &CompileOptions,
+) -> Result<(), Diagnostic> {
+    let temp_dir = tempfile::tempdir().map_err(|e| tr!("create-temp-dir-failed", error = e))?;
+    let object_file_paths = write_objects(temp_dir.path(), &[object_code], output_path, options)?;
+
+    let host = host_triple();
+    let target = options.target.as_deref().unwrap_or(&host);
+
+    let runtime = runtime::write_runtime(temp_dir.path(), options.target.as_deref(), &host)?;
+
+    fs::copy(&runtime, output_path)
+        .map_err(|e| tr!("write-output-failed", path = output_path.display(), error = e))?;
+
+    let mut command = cc::Build::new()
+        .target(target)
+        .host(&host)
+        .opt_level(0)
+        .cargo_metadata(false)
+        .try_get_archiver()
+        .map_err(|e| tr!("linker-not-found", linker = "ar", error = e))?;
+
+    command.arg("rs").arg(output_path).args(&object_file_paths);
+
+    run_link_command(command)
+}
+
+/// 运行链接器或归档工具, 失败时带上完整命令与其错误输出
+fn run_link_command(mut command: Command) -> Result<(), Diagnostic> {
+    let output = command.output().map_err(|e| {
+        tr!("linker-not-found", linker = command.get_program().to_string_lossy(), error = e)
+    })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        return Err(Diagnostic::error(tr!("link-failed", status = output.status))
+            .with_note(format!("{command:?}"))
+            .with_note(stderr.trim()));
+    }
 
-    fs::remove_file(lib_path)?;
     Ok(())
 }
 
-pub fn get_platform_width() -> usize {
-    #[cfg(target_pointer_width = "64")]
-    return 64;
+/// `--linker` 指定的链接器, 否则由 cc 选择目标平台的 C 编译器 (交叉编译时如 aarch64-linux-gnu-gcc)
+fn linker_command(options: &CompileOptions, target: &str, host: &str) -> Result<Command, Diagnostic> {
+    if let Some(linker) = &options.linker {
+        return Ok(Command::new(linker));
+    }
 
-    #[cfg(target_pointer_width = "32")]
-    return 32;
+    let compiler = cc::Build::new()
+        .target(target)
+        .host(host)
+        .opt_level(0)
+        .cargo_metadata(false)
+        .try_get_compiler()
+        .map_err(|e| tr!("linker-not-found", linker = "cc", error = e))?;
 
-    #[cfg(target_pointer_width = "16")]
-    return 16;
+    Ok(compiler.to_command())
 }
 
-impl CompileState for GlobalState<'_> {
+impl<M: Module> CompileState for GlobalState<'_, M> {
+    type Module = M;
+
     fn get_target_isa(&self) -> Arc<dyn TargetIsa> {
         self.target_isa.clone()
     }
 
-    fn get_module(&mut self) -> &mut ObjectModule {
+    fn get_module(&mut self) -> &mut M {
         self.module
     }
 
@@ -263,14 +507,20 @@ impl CompileState for GlobalState<'_> {
     fn get_arc_release(&self) -> FuncId {
         self.arc_release
     }
+
+    fn get_diagnostics(&mut self) -> &mut Diagnostics {
+        self.diagnostics
+    }
 }
 
-impl CompileState for FunctionState<'_> {
+impl<M: Module> CompileState for FunctionState<'_, M> {
+    type Module = M;
+
     fn get_target_isa(&self) -> Arc<dyn TargetIsa> {
         self.target_isa.clone()
     }
 
-    fn get_module(&mut self) -> &mut ObjectModule {
+    fn get_module(&mut self) -> &mut M {
         self.module
     }
 
@@ -301,4 +551,8 @@ impl CompileState for FunctionState<'_> {
     fn get_arc_release(&self) -> FuncId {
         self.arc_release
     }
+
+    fn get_diagnostics(&mut self) -> &mut Diagnostics {
+        self.diagnostics
+    }
 }
@@ -0,0 +1,80 @@
+//! 并行代码生成: 函数体的 IR 按顺序生成之后, 在线程池中分别编译为机器码,
+//! 再按声明顺序定义到 module 中, 生成的目标文件与线程数无关
+
+use std::{num::NonZeroUsize, sync::Mutex, thread};
+
+use cranelift_codegen::{Context, control::ControlPlane, isa::TargetIsa};
+use cranelift_module::FuncId;
+
+use crate::compiler::CodeDump;
+
+/// 已生成 IR、等待编译的函数, 各自带有独立的 Context
+pub struct PendingFunction {
+    pub name: String,
+    pub func_id: FuncId,
+    pub ctx: Context,
+    /// 增量编译缓存的键, 未启用缓存时为 None
+    pub key: Option<u64>,
+    /// 编译之前的 IR (`--emit clif`)
+    pub clif: Option<String>,
+    /// 编译失败时的错误信息
+    pub error: Option<String>,
+}
+
+impl PendingFunction {
+    pub fn new(
+        name: &str,
+        func_id: FuncId,
+        mut ctx: Context,
+        key: Option<u64>,
+        dump: &CodeDump,
+    ) -> Self {
+        let clif = dump.clif.then(|| ctx.func.display().to_string());
+
+        ctx.set_disasm(dump.asm);
+
+        Self {
+            name: name.to_string(),
+            func_id,
+            ctx,
+            key,
+            clif,
+            error: None,
+        }
+    }
+
+    pub fn compile(&mut self, isa: &dyn TargetIsa) {
+        if let Err(e) = self.ctx.compile(isa, &mut ControlPlane::default()) {
+            self.error = Some(e.inner.to_string());
+        }
+    }
+}
+
+/// 在 `jobs` 个线程中编译所有函数, None 表示使用可用的 CPU 数
+pub fn compile_all(isa: &dyn TargetIsa, functions: &mut [PendingFunction], jobs: Option<usize>) {
+    let jobs = jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get))
+        .clamp(1, functions.len().max(1));
+
+    if jobs == 1 {
+        functions.iter_mut().for_each(|it| it.compile(isa));
+        return;
+    }
+
+    // 每个线程从队列中取下一个函数, 函数之间没有依赖
+    let queue = Mutex::new(functions.iter_mut());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| {
+                loop {
+                    let Some(function) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+
+                    function.compile(isa);
+                }
+            });
+        }
+    });
+}
@@ -0,0 +1,77 @@
+//! 链接时使用的运行时 (`runtime/*.c`)
+//!
+//! build.rs 为 antc 自身的目标平台编译好运行时库并嵌入 antc, 本机编译时直接写出使用;
+//! 交叉编译时用嵌入的源码为目标平台重新编译
+//!
+//! 同一份运行时库也链接进 antc, JIT 编译的代码直接调用其中的函数
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{diagnostic::Diagnostic, tr};
+
+/// 为 antc 自身的目标平台编译好的运行时库
+const RUNTIME_LIB: &[u8] = include_bytes!(env!("ANT_RUNTIME_LIB"));
+
+/// 运行时源码: (文件名, 内容)
+const RUNTIME_SOURCES: &[(&str, &str)] =
+    include!(concat!(env!("OUT_DIR"), "/runtime_sources.rs"));
+
+// 链接进 antc 的运行时函数, 由 build.rs 生成 `jit_symbols`
+include!(concat!(env!("OUT_DIR"), "/runtime_symbols.rs"));
+
+/// 在 `dir` 中写出运行时库, 返回库文件路径
+///
+/// target 为 None 表示本机编译
+pub fn write_runtime(
+    dir: &Path,
+    target: Option<&str>,
+    host: &str,
+) -> Result<PathBuf, Diagnostic> {
+    let Some(target) = target else {
+        let path = dir.join("libant_runtime.a");
+
+        fs::write(&path, RUNTIME_LIB)
+            .map_err(|e| tr!("write-output-failed", path = path.display(), error = e))?;
+
+        return Ok(path);
+    };
+
+    let src_dir = dir.join("runtime");
+    fs::create_dir_all(&src_dir)
+        .map_err(|e| tr!("write-output-failed", path = src_dir.display(), error = e))?;
+
+    let mut build = cc::Build::new();
+
+    for (name, source) in RUNTIME_SOURCES {
+        let path = src_dir.join(name);
+
+        fs::write(&path, source)
+            .map_err(|e| tr!("write-output-failed", path = path.display(), error = e))?;
+
+        build.file(path);
+    }
+
+    build
+        .target(target)
+        .host(host)
+        .opt_level(2)
+        .debug(false)
+        .out_dir(dir)
+        .cargo_metadata(false)
+        .cargo_warnings(false)
+        .try_compile("ant_runtime")
+        .map_err(|e| {
+            Diagnostic::error(tr!("runtime-compile-failed", target = target))
+                .with_note(e.to_string())
+        })?;
+
+    // 与 build.rs 一致: MSVC 下为 ant_runtime.lib
+    if target.contains("msvc") {
+        Ok(dir.join("ant_runtime.lib"))
+    } else {
+        Ok(dir.join("libant_runtime.a"))
+    }
+}
@@ -2,6 +2,13 @@ use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
 
 use crate::traits::NeedGc;
 
+/// 带命名空间的符号名
+///
+/// 模块中的顶层定义为 `模块::名字` (如 `math::ops::add`), 方法为 `类型::方法` (如 `Point::len`)
+pub fn mangle(namespace: &str, name: &str) -> String {
+    format!("{namespace}::{name}")
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SymbolScope {
     Local,
@@ -22,6 +29,8 @@ pub struct StructLayout {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SymbolTy {
     Var,
+    /// 保存在可写数据对象中的全局变量 (REPL 中的 `let`), 数据对象里存放的是值本身
+    Static,
     Function,
     Struct(StructLayout),
 }
@@ -205,6 +214,15 @@ impl SymbolTable {
         symbol
     }
 
+    pub fn define_static(&mut self, name: &str) -> Symbol {
+        let mut symbol = self.define(name);
+        symbol.symbol_ty = SymbolTy::Static;
+
+        self.map.insert(name.into(), symbol.clone());
+
+        symbol
+    }
+
     pub fn define_func(&mut self, name: &str) -> Symbol {
         let symbol = Symbol::create_func(
             name.into(),
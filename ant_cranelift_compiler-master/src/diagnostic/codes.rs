@@ -0,0 +1,128 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{i18n::{Lang, current_lang}, tr};
+
+/// 稳定的错误码, 可通过 `antc --explain <CODE>` 查看详细说明
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// 对类型名赋值
+    E0001,
+    /// 结构体中不存在该字段
+    E0002,
+    /// 不支持的 abi
+    E0003,
+    /// 常量的值不是字面量
+    E0004,
+    /// 未定义的结构体
+    E0005,
+    /// 全局变量不在 data map 中
+    E0006,
+    /// 未定义的变量
+    E0007,
+    /// 后端尚未支持的语法结构
+    E0008,
+    /// 赋值时类型不匹配
+    E0009,
+    /// 模块之间循环导入
+    E0010,
+    /// 找不到导入的模块
+    E0011,
+    /// 两个导入的模块使用同一个别名
+    E0012,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 12] = [
+        ErrorCode::E0001,
+        ErrorCode::E0002,
+        ErrorCode::E0003,
+        ErrorCode::E0004,
+        ErrorCode::E0005,
+        ErrorCode::E0006,
+        ErrorCode::E0007,
+        ErrorCode::E0008,
+        ErrorCode::E0009,
+        ErrorCode::E0010,
+        ErrorCode::E0011,
+        ErrorCode::E0012,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::E0001 => "E0001",
+            ErrorCode::E0002 => "E0002",
+            ErrorCode::E0003 => "E0003",
+            ErrorCode::E0004 => "E0004",
+            ErrorCode::E0005 => "E0005",
+            ErrorCode::E0006 => "E0006",
+            ErrorCode::E0007 => "E0007",
+            ErrorCode::E0008 => "E0008",
+            ErrorCode::E0009 => "E0009",
+            ErrorCode::E0010 => "E0010",
+            ErrorCode::E0011 => "E0011",
+            ErrorCode::E0012 => "E0012",
+        }
+    }
+
+    /// 当前语言下的详细说明 (含错误与正确的 Ant 示例), 编译期嵌入二进制
+    pub fn explanation(&self) -> &'static str {
+        self.explanation_in(current_lang())
+    }
+
+    pub fn explanation_in(&self, lang: Lang) -> &'static str {
+        macro_rules! explanations {
+            ($($code:ident),+) => {
+                match (lang, self) {
+                    $(
+                        (Lang::ZhCN, ErrorCode::$code) => include_str!(
+                            concat!("explanations/zh_CN/", stringify!($code), ".md")
+                        ),
+                        (Lang::EnUS, ErrorCode::$code) => include_str!(
+                            concat!("explanations/en_US/", stringify!($code), ".md")
+                        ),
+                    )+
+                }
+            };
+        }
+
+        explanations!(
+            E0001, E0002, E0003, E0004, E0005, E0006, E0007, E0008, E0009, E0010, E0011, E0012
+        )
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.trim().to_ascii_uppercase();
+
+        Self::ALL
+            .into_iter()
+            .find(|it| it.as_str() == code)
+            .ok_or_else(|| tr!("unknown-error-code", code = s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{diagnostic::codes::ErrorCode, i18n::Lang};
+
+    #[test]
+    fn every_code_has_examples() {
+        for code in ErrorCode::ALL {
+            assert_eq!(code.as_str().parse::<ErrorCode>(), Ok(code));
+
+            for lang in [Lang::ZhCN, Lang::EnUS] {
+                let explanation = code.explanation_in(lang);
+                assert_eq!(explanation.matches("```ant").count(), 2, "{code} {lang:?}");
+            }
+        }
+    }
+}
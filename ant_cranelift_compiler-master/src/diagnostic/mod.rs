@@ -0,0 +1,432 @@
+pub mod codes;
+
+use std::{collections::HashMap, fmt::Display, sync::Arc};
+
+use ant_token::token::Token;
+use serde_json::json;
+
+use crate::{diagnostic::codes::ErrorCode, traits::NoRepeatPush, tr};
+
+/// 编译流程中的各个阶段, 每个阶段失败时使用不同的退出码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Io,
+    Lexer,
+    Parser,
+    TypeChecker,
+    Monomorphizer,
+    Codegen,
+    Link,
+    /// 模块的导入与名字解析
+    Import,
+}
+
+impl Stage {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Stage::Io => 1,
+            Stage::Lexer => 2,
+            Stage::Parser => 3,
+            Stage::TypeChecker => 4,
+            Stage::Monomorphizer => 5,
+            Stage::Codegen => 6,
+            Stage::Link => 7,
+            Stage::Import => 8,
+        }
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Stage::Io => "io",
+            Stage::Lexer => "lexer",
+            Stage::Parser => "parser",
+            Stage::TypeChecker => "type checker",
+            Stage::Monomorphizer => "monomorphizer",
+            Stage::Codegen => "codegen",
+            Stage::Link => "link",
+            Stage::Import => "import",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// 源码中的一段位置 (行列均从 1 开始)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub file: Arc<str>,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    /// 由 Token 得到位置, 伪造的 Token (行号为 0) 没有位置
+    pub fn from_token(token: &Token) -> Option<Self> {
+        let line = token.line as usize;
+        let column = token.column as usize;
+
+        if line == 0 {
+            return None;
+        }
+
+        Some(Self {
+            file: token.file.to_string().into(),
+            line,
+            column,
+            end_line: line,
+            end_column: column + token.value.chars().count().max(1),
+        })
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// 一条诊断信息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<ErrorCode>,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            span: None,
+            notes: vec![],
+        }
+    }
+
+    /// 合法的 TypedAnt 代码, 但后端尚未支持
+    pub fn unsupported(construct: impl Display) -> Self {
+        Self::error(tr!("unsupported-construct", construct = construct))
+            .with_code(ErrorCode::E0008)
+            .with_note(tr!("unsupported-note"))
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// `error[E0001]` 形式的标题
+    fn title(&self) -> String {
+        match self.code {
+            Some(code) => format!("{}[{code}]", self.severity),
+            None => self.severity.to_string(),
+        }
+    }
+
+    pub fn with_span(mut self, span: Option<Span>) -> Self {
+        self.span = span;
+        self
+    }
+
+    pub fn with_token(self, token: &Token) -> Self {
+        self.with_span(Span::from_token(token))
+    }
+
+    /// 仅在还没有位置时补充位置, 保证内层表达式给出的位置不会被外层覆盖
+    pub fn or_token(self, token: &Token) -> Self {
+        if self.span.is_some() {
+            return self;
+        }
+
+        self.with_token(token)
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// 以 rustc 风格渲染诊断信息 (带源码片段)
+    pub fn render(&self, sources: &SourceMap) -> String {
+        let mut out = format!("{}: {}\n", self.title(), self.message);
+
+        let Some(span) = &self.span else {
+            for note in &self.notes {
+                out.push_str(&format!("  = note: {note}\n"));
+            }
+
+            return out;
+        };
+
+        let gutter = " ".repeat(span.line.to_string().len());
+
+        out.push_str(&format!("{gutter}--> {span}\n"));
+
+        if let Some(line) = sources.line(&span.file, span.line) {
+            let underline_start = span.column.saturating_sub(1);
+            let underline_len = if span.end_line == span.line {
+                span.end_column.saturating_sub(span.column).max(1)
+            } else {
+                line.chars().count().saturating_sub(underline_start).max(1)
+            };
+
+            // 保留制表符, 让下划线与源码对齐
+            let padding = line
+                .chars()
+                .take(underline_start)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+
+            out.push_str(&format!("{gutter} |\n"));
+            out.push_str(&format!("{} | {line}\n", span.line));
+            out.push_str(&format!(
+                "{gutter} | {padding}{}\n",
+                "^".repeat(underline_len)
+            ));
+        }
+
+        for note in &self.notes {
+            out.push_str(&format!("{gutter} = note: {note}\n"));
+        }
+
+        out
+    }
+}
+
+impl Diagnostic {
+    /// 单行 JSON, 供编辑器插件和 CI 解析
+    pub fn to_json(&self, stage: Stage) -> String {
+        let range = self.span.as_ref().map(|span| {
+            json!({
+                "start": { "line": span.line, "column": span.column },
+                "end": { "line": span.end_line, "column": span.end_column },
+            })
+        });
+
+        json!({
+            "severity": self.severity.to_string(),
+            "code": self.code.map(|it| it.as_str()),
+            "stage": stage.to_string(),
+            "message": self.message,
+            "file": self.span.as_ref().map(|span| span.file.to_string()),
+            "range": range,
+            "notes": self.notes,
+        })
+        .to_string()
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "{span}: {}: {}", self.title(), self.message),
+            None => write!(f, "{}: {}", self.title(), self.message),
+        }
+    }
+}
+
+impl From<String> for Diagnostic {
+    fn from(value: String) -> Self {
+        Self::error(value)
+    }
+}
+
+impl From<&str> for Diagnostic {
+    fn from(value: &str) -> Self {
+        Self::error(value)
+    }
+}
+
+/// 某一阶段失败后返回给驱动程序的错误
+#[derive(Debug, Clone)]
+pub struct CompileError {
+    pub stage: Stage,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CompileError {
+    pub fn new(stage: Stage, diagnostic: impl Into<Diagnostic>) -> Self {
+        Self {
+            stage,
+            diagnostics: vec![diagnostic.into()],
+        }
+    }
+
+    pub fn from_diagnostics(stage: Stage, diagnostics: Vec<Diagnostic>) -> Self {
+        Self { stage, diagnostics }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.stage.exit_code()
+    }
+
+    /// 类似 rustc 的 "aborting due to N previous errors"
+    pub fn summary(&self) -> String {
+        let count = self
+            .diagnostics
+            .iter()
+            .filter(|it| it.severity == Severity::Error)
+            .count();
+
+        let mut summary = if count == 1 {
+            format!("error: {}", tr!("aborting-one"))
+        } else {
+            format!("error: {}", tr!("aborting-many", count = count))
+        };
+
+        let mut codes = Vec::new();
+        for code in self.diagnostics.iter().filter_map(|it| it.code) {
+            codes.push_no_repeat(code);
+        }
+
+        if let Some(first) = codes.first() {
+            let codes = codes
+                .iter()
+                .map(|it| it.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            summary.push_str("\n\n");
+            summary.push_str(&tr!("explain-hint", codes = codes, code = first));
+        }
+
+        summary
+    }
+
+    pub fn render(&self, sources: &SourceMap) -> String {
+        self.diagnostics
+            .iter()
+            .map(|it| it.render(sources))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn render_json(&self) -> String {
+        self.diagnostics
+            .iter()
+            .map(|it| it.to_json(self.stage))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// 收集诊断信息, 使代码生成在遇到错误后可以继续, 一次报告所有错误
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.items.push(diagnostic);
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<Diagnostic> {
+        self.items
+    }
+}
+
+/// 文件名 -> 源码, 渲染诊断时用于取出对应行
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: HashMap<Arc<str>, Arc<str>>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, file: Arc<str>, content: Arc<str>) {
+        self.files.insert(file, content);
+    }
+
+    pub fn line(&self, file: &str, line: usize) -> Option<&str> {
+        self.files
+            .get(file)?
+            .lines()
+            .nth(line.checked_sub(1)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::{Diagnostic, SourceMap, Span, Stage};
+
+    #[test]
+    fn render_with_snippet() {
+        let mut sources = SourceMap::new();
+        sources.add("a.ant".into(), "func main() -> i32 {\n    foo\n}".into());
+
+        let diagnostic = Diagnostic::error("undefined variable: foo")
+            .with_span(Some(Span {
+                file: "a.ant".into(),
+                line: 2,
+                column: 5,
+                end_line: 2,
+                end_column: 8,
+            }))
+            .with_note("declare it with `let` first");
+
+        assert_eq!(
+            diagnostic.render(&sources),
+            "error: undefined variable: foo\n \
+             --> a.ant:2:5\n  \
+             |\n\
+             2 |     foo\n  \
+             |     ^^^\n  \
+             = note: declare it with `let` first\n"
+        );
+    }
+
+    #[test]
+    fn json_is_single_line() {
+        let diagnostic = Diagnostic::error("unsupported abi: \"rust\"\nhere")
+            .with_note("only \"C\" is supported");
+
+        let json = diagnostic.to_json(Stage::Codegen);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert!(!json.contains('\n'));
+        assert_eq!(value["severity"], "error");
+        assert_eq!(value["stage"], "codegen");
+        assert_eq!(value["file"], serde_json::Value::Null);
+        assert_eq!(value["notes"][0], "only \"C\" is supported");
+    }
+}
@@ -0,0 +1,117 @@
+pub const MESSAGES: &[(&str, &str)] = &[
+    // 命令行
+    ("cli-about", "TypedAnt Compiler"),
+    ("cli-file", "Input file path"),
+    ("cli-output", "Output path"),
+    ("cli-opt-level", "Optimization level (0-3, s, z)"),
+    ("cli-link", "Files passed to the linker as-is (.o, .a, .so)"),
+    ("cli-lib-path", "Library search path"),
+    ("cli-lib", "Link a library by name (e.g. `-l m`)"),
+    ("cli-link-arg", "Pass an argument to the linker verbatim"),
+    ("cli-linker", "Linker to use (defaults to the C compiler for the target)"),
+    ("cli-static", "Link statically (default on Windows)"),
+    ("cli-dynamic", "Link dynamically (default everywhere except Windows)"),
+    ("cli-pie", "Produce a position-independent executable (default everywhere except Windows)"),
+    ("cli-no-pie", "Produce a position-dependent executable"),
+    ("cli-script-mode", "Compile top-level statements into `main` (script mode)"),
+    ("cli-error-format", "Diagnostic output format (human: annotated snippets, json: one JSON object per line)"),
+    ("cli-explain", "Print a detailed explanation of an error code (e.g. E0001)"),
+    ("cli-emit", "Output type (obj: object file, clif: Cranelift IR, asm: disassembly, exe: executable, typed-ast: checked AST)"),
+    ("cli-crate-type", "Kind of artifact (bin: executable, staticlib: static library, cdylib: shared library; libraries come with a C header)"),
+    ("cli-save-temps", "Keep intermediate files (such as the `.o` object) next to the output"),
+    ("cli-incremental", "Incremental compilation cache directory; unchanged functions reuse their cached machine code"),
+    ("cli-run", "Compile the program in-process with a JIT and run `main`; without a file, build and run the project described by `Ant.toml`"),
+    ("cli-build", "Build the project described by `Ant.toml` into `target/<profile>/<name>`"),
+    ("cli-release", "Use the release profile"),
+    ("cli-manifest-path", "Path to `Ant.toml`, searched upwards from the current directory by default"),
+    ("cli-run-args", "Arguments passed to the program"),
+    ("cli-repl", "Interactive REPL: compile and run statements and expressions one at a time"),
+    ("cli-target", "Target triple (e.g. aarch64-unknown-linux-gnu), defaults to the host"),
+    ("cli-target-cpu", "CPU features the generated code may use, defaults to native for host builds"),
+    ("cli-jobs", "Number of threads compiling functions in parallel, defaults to the number of available CPUs"),
+    ("cli-codegen", "Raw Cranelift setting, e.g. `-C preserve_frame_pointers=true`"),
+    ("cli-lang", "Language of compiler messages (defaults to the LANG environment variable)"),
+    ("invalid-opt-level", "invalid optimization level: {level}. possible values: 0, 1, 2, 3, s, z"),
+    ("invalid-codegen-option", "invalid -C option: {option}. expected KEY=VALUE"),
+    ("unknown-error-code", "unknown error code: {code}"),
+    // 驱动程序
+    ("manifest-not-found", "could not find `Ant.toml` in the current directory or any parent directory"),
+    ("invalid-manifest", "failed to parse manifest `{path}`"),
+    ("run-program-failed", "failed to run `{path}`: {error}"),
+    ("no-input-file", "no input file"),
+    ("file-not-exists", "file does not exist: {path}"),
+    ("read-file-error", "failed to read file: {error}"),
+    ("invalid-import", "invalid import: `{import}`. expected `import \"path.ant\"` or `import foo.bar`"),
+    ("unresolved-import", "cannot find module `{path}`"),
+    ("import-cycle", "importing `{path}` creates a cycle"),
+    ("duplicate-import-alias", "`{alias}` already refers to another imported module"),
+    ("write-output-failed", "failed to write `{path}`: {error}"),
+    ("aborting-one", "aborting due to 1 previous error"),
+    ("aborting-many", "aborting due to {count} previous errors"),
+    ("explain-hint", "Some errors have detailed explanations: {codes}.\nFor more information about an error, try `antc --explain {code}`."),
+    ("repl-welcome", "TypedAnt REPL. Type `:quit` to exit."),
+    // 不支持的语法结构
+    ("unsupported-construct", "unsupported construct: {construct}"),
+    ("unsupported-note", "this construct is not supported by the cranelift backend yet"),
+    ("construct-type", "type `{ty}`"),
+    ("construct-field-type", "field of type `{ty}`"),
+    ("construct-anonymous-top-level-function", "anonymous top-level function"),
+    ("construct-anonymous-function", "anonymous function"),
+    ("construct-top-level-statement", "top-level statement `{stmt}`"),
+    ("construct-statement", "statement `{stmt}`"),
+    ("construct-impl-body", "impl body `{block}`"),
+    ("construct-expression", "expression `{expr}`"),
+    ("construct-operator", "operator `{op}` on `{left}` and `{right}`"),
+    ("construct-prefix-operator", "prefix operator `{op}` on `{ty}`"),
+    // 代码生成
+    ("undefined-struct", "undefined struct: `{name}`"),
+    ("not-a-struct", "not a struct: `{name}`"),
+    ("not-a-function", "not a function: `{ty}`"),
+    ("not-a-constant", "expression `{expr}` is not a constant"),
+    ("declare-const-failed", "failed to declare const `{name}`: {error}"),
+    ("define-const-failed", "failed to define const `{name}`: {error}"),
+    ("declare-variable-failed", "failed to declare variable `{name}`: {error}"),
+    ("declare-function-failed", "failed to declare `{name}`: {error}"),
+    ("define-function-failed", "failed to define `{name}`: {error}"),
+    ("function-not-compiled", "no machine code was generated"),
+    ("declare-string-failed", "failed to declare string literal: {error}"),
+    ("define-string-failed", "failed to define string literal: {error}"),
+    ("unsupported-abi", "unsupported abi: {abi}"),
+    ("type-not-found", "cannot find type `{name}` in this scope"),
+    ("variable-not-in-data-map", "variable `{name}` not in data map"),
+    ("undefined-variable", "undefined variable: `{name}`"),
+    ("field-not-found", "field `{field}` not found in struct `{name}`"),
+    ("field-access-on-non-struct", "field access on non-struct type `{ty}`"),
+    ("field-set-on-non-struct", "field set on non-struct type `{ty}`"),
+    ("mismatched-types", "mismatched types: expected `{expected}`, found `{found}`"),
+    ("assign-to-type", "assign to a type: `{name}`"),
+    ("invalid-assign-target", "assign target must be ident or field"),
+    ("call-non-function", "call to a non-function type `{ty}`"),
+    ("verifier-errors", "verifier errors"),
+    ("jit-finalize-failed", "failed to finalize JIT code: {error}"),
+    ("main-not-found", "no `main` function to run"),
+    ("main-bad-signature", "`main` must be `main()` or `main(argc, argv)`, found `{signature}`"),
+    ("function-not-found", "no function named `{name}` is defined"),
+    ("function-signature-mismatch", "the Rust type does not match the signature of `{name}`: `{expected}`"),
+    ("invalid-target", "invalid target triple `{target}`: {error}"),
+    ("unsupported-target", "unsupported target `{target}`: {error}"),
+    ("host-isa-unavailable", "the host platform is not supported: {error}"),
+    ("target-cpu-native-cross", "--target-cpu native cannot be used when cross-compiling to `{target}`"),
+    ("unsupported-target-cpu", "CPU `{cpu}` is not supported by target `{target}`: {error}"),
+    ("invalid-codegen-flag", "invalid Cranelift setting `{key}={value}`: {error}"),
+    ("jit-cross-target", "JIT can only compile for the host, cannot target `{target}`"),
+    ("emit-object-failed", "failed to emit object file: {error}"),
+    ("create-object-builder-failed", "failed to create object file: {error}"),
+    // 链接
+    ("create-temp-dir-failed", "failed to create temporary directory: {error}"),
+    ("linker-not-found", "failed to run linker `{linker}`: {error}"),
+    ("runtime-compile-failed", "failed to compile the runtime for `{target}`"),
+    ("link-failed", "linking failed ({status})"),
+];
+
+pub fn message(key: &str) -> Option<&'static str> {
+    MESSAGES
+        .iter()
+        .find(|(it, _)| *it == key)
+        .map(|(_, message)| *message)
+}
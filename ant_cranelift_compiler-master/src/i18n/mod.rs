@@ -0,0 +1,115 @@
+//! 编译器诊断信息与命令行帮助的多语言消息表
+//!
+//! 语言由 `--lang` 参数或 `LANG` 环境变量选择, 目前提供 zh_CN 和 en_US
+
+mod en_us;
+mod zh_cn;
+
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    #[value(name = "zh_CN", alias = "zh")]
+    ZhCN,
+    #[value(name = "en_US", alias = "en")]
+    EnUS,
+}
+
+impl Lang {
+    /// 解析 `zh_CN.UTF-8` / `en_US` 这类 locale 字符串
+    pub fn from_locale(locale: &str) -> Option<Self> {
+        let locale = locale.to_ascii_lowercase();
+
+        if locale.starts_with("zh") {
+            Some(Lang::ZhCN)
+        } else if locale.starts_with("en") || locale == "c" || locale == "posix" {
+            Some(Lang::EnUS)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LANG"]
+            .iter()
+            .filter_map(|it| std::env::var(it).ok())
+            .find_map(|it| Self::from_locale(&it))
+            .unwrap_or(Lang::EnUS)
+    }
+
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            Lang::ZhCN => "zh_CN",
+            Lang::EnUS => "en_US",
+        }
+    }
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+/// 设置输出语言, 只有第一次调用生效
+pub fn set_lang(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+pub fn current_lang() -> Lang {
+    *LANG.get_or_init(Lang::from_env)
+}
+
+/// 取出当前语言下的消息模板, 缺失时回退到 en_US
+pub fn message(key: &str) -> &'static str {
+    let message = match current_lang() {
+        Lang::ZhCN => zh_cn::message(key),
+        Lang::EnUS => en_us::message(key),
+    };
+
+    message.or_else(|| en_us::message(key)).unwrap_or("<missing message>")
+}
+
+/// 按 key 取出当前语言的消息, 并替换模板中的 `{name}` 占位符
+///
+/// ```ignore
+/// tr!("undefined-struct", name = "Point")
+/// ```
+#[macro_export]
+macro_rules! tr {
+    ($key:literal) => {
+        $crate::i18n::message($key).to_string()
+    };
+    ($key:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut message = $crate::i18n::message($key).to_string();
+        $(
+            message = message.replace(
+                concat!("{", stringify!($name), "}"),
+                &$value.to_string(),
+            );
+        )+
+        message
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::i18n::{Lang, en_us, zh_cn};
+
+    #[test]
+    fn locale_detection() {
+        assert_eq!(Lang::from_locale("zh_CN.UTF-8"), Some(Lang::ZhCN));
+        assert_eq!(Lang::from_locale("en_US.UTF-8"), Some(Lang::EnUS));
+        assert_eq!(Lang::from_locale("C"), Some(Lang::EnUS));
+        assert_eq!(Lang::from_locale("de_DE.UTF-8"), None);
+    }
+
+    #[test]
+    fn catalogs_have_same_keys() {
+        for (key, _) in en_us::MESSAGES {
+            assert!(zh_cn::message(key).is_some(), "zh_CN is missing `{key}`");
+        }
+
+        for (key, _) in zh_cn::MESSAGES {
+            assert!(en_us::message(key).is_some(), "en_US is missing `{key}`");
+        }
+    }
+}
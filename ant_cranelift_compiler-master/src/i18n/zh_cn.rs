@@ -0,0 +1,117 @@
+pub const MESSAGES: &[(&str, &str)] = &[
+    // 命令行
+    ("cli-about", "TypedAnt 编译器"),
+    ("cli-file", "输入文件路径"),
+    ("cli-output", "输出路径"),
+    ("cli-opt-level", "优化级别 (0-3, s, z)"),
+    ("cli-link", "直接交给链接器的文件 (.o, .a, .so)"),
+    ("cli-lib-path", "库搜索路径"),
+    ("cli-lib", "按名字链接的库 (如 `-l m`)"),
+    ("cli-link-arg", "原样传给链接器的参数"),
+    ("cli-linker", "链接器 (默认为目标平台的 C 编译器)"),
+    ("cli-static", "静态链接 (Windows 的默认方式)"),
+    ("cli-dynamic", "动态链接 (Windows 以外的默认方式)"),
+    ("cli-pie", "生成位置无关可执行文件 (Windows 以外的默认方式)"),
+    ("cli-no-pie", "生成位置相关的可执行文件"),
+    ("cli-script-mode", "脚本模式开关 (将顶层语句编译进 `main`)"),
+    ("cli-error-format", "诊断信息输出格式 (human: 带源码片段, json: 每行一个 JSON 对象)"),
+    ("cli-explain", "输出错误码的详细说明 (如 E0001)"),
+    ("cli-emit", "输出类型 (obj: 目标文件, clif: Cranelift IR, asm: 反汇编, exe: 可执行文件, typed-ast: 类型检查后的 AST)"),
+    ("cli-crate-type", "编译产物的种类 (bin: 可执行文件, staticlib: 静态库, cdylib: 动态库; 库会附带 C 头文件)"),
+    ("cli-save-temps", "在输出文件旁保留中间文件 (如 `.o` 目标文件)"),
+    ("cli-incremental", "增量编译缓存目录, 未变化的函数直接使用缓存的机器码"),
+    ("cli-run", "以 JIT 方式在进程内编译程序并直接运行 `main`; 不指定文件时构建 `Ant.toml` 描述的项目并运行"),
+    ("cli-build", "按 `Ant.toml` 构建项目, 输出到 `target/<profile>/<name>`"),
+    ("cli-release", "使用 release profile"),
+    ("cli-manifest-path", "`Ant.toml` 的路径, 默认从当前目录向上查找"),
+    ("cli-run-args", "传给程序的参数"),
+    ("cli-repl", "交互式 REPL, 逐条编译并执行输入的语句与表达式"),
+    ("cli-target", "目标平台 (如 aarch64-unknown-linux-gnu), 默认为本机"),
+    ("cli-target-cpu", "生成代码可以使用的 CPU 特性, 本机编译默认为 native"),
+    ("cli-jobs", "并行编译函数的线程数, 默认为可用的 CPU 数"),
+    ("cli-codegen", "直接传给 Cranelift 的设置, 如 `-C preserve_frame_pointers=true`"),
+    ("cli-lang", "编译器消息的语言 (默认读取 LANG 环境变量)"),
+    ("invalid-opt-level", "无效的优化级别: {level}. 可选值: 0, 1, 2, 3, s, z"),
+    ("invalid-codegen-option", "无效的 -C 选项: {option}. 格式应为 KEY=VALUE"),
+    ("unknown-error-code", "未知的错误码: {code}"),
+    // 驱动程序
+    ("manifest-not-found", "在当前目录及其上级目录中找不到 `Ant.toml`"),
+    ("invalid-manifest", "无法解析清单 `{path}`"),
+    ("run-program-failed", "无法运行 `{path}`: {error}"),
+    ("no-input-file", "没有输入文件"),
+    ("file-not-exists", "文件不存在: {path}"),
+    ("read-file-error", "读取文件失败: {error}"),
+    ("invalid-import", "无效的导入: `{import}`. 应为 `import \"路径.ant\"` 或 `import foo.bar`"),
+    ("unresolved-import", "找不到模块 `{path}`"),
+    ("import-cycle", "导入 `{path}` 形成了循环"),
+    ("duplicate-import-alias", "`{alias}` 已经指向另一个导入的模块"),
+    ("write-output-failed", "写入 `{path}` 失败: {error}"),
+    ("aborting-one", "由于之前的 1 个错误, 编译中止"),
+    ("aborting-many", "由于之前的 {count} 个错误, 编译中止"),
+    ("explain-hint", "部分错误有详细说明: {codes}。\n如需了解某个错误的详细信息, 请尝试 `antc --explain {code}`。"),
+    ("repl-welcome", "TypedAnt REPL, 输入 `:quit` 退出。"),
+    // 不支持的语法结构
+    ("unsupported-construct", "不支持的语法结构: {construct}"),
+    ("unsupported-note", "cranelift 后端暂不支持该语法结构"),
+    ("construct-type", "类型 `{ty}`"),
+    ("construct-field-type", "类型为 `{ty}` 的字段"),
+    ("construct-anonymous-top-level-function", "顶层匿名函数"),
+    ("construct-anonymous-function", "匿名函数"),
+    ("construct-top-level-statement", "顶层语句 `{stmt}`"),
+    ("construct-statement", "语句 `{stmt}`"),
+    ("construct-impl-body", "impl 块 `{block}`"),
+    ("construct-expression", "表达式 `{expr}`"),
+    ("construct-operator", "`{left}` 与 `{right}` 之间的运算符 `{op}`"),
+    ("construct-prefix-operator", "`{ty}` 上的前缀运算符 `{op}`"),
+    // 代码生成
+    ("undefined-struct", "未定义的结构体: `{name}`"),
+    ("not-a-struct", "不是结构体: `{name}`"),
+    ("not-a-function", "不是函数: `{ty}`"),
+    ("not-a-constant", "表达式 `{expr}` 不是常量"),
+    ("declare-const-failed", "声明常量 `{name}` 失败: {error}"),
+    ("define-const-failed", "定义常量 `{name}` 失败: {error}"),
+    ("declare-variable-failed", "声明变量 `{name}` 失败: {error}"),
+    ("declare-function-failed", "声明 `{name}` 失败: {error}"),
+    ("define-function-failed", "定义 `{name}` 失败: {error}"),
+    ("function-not-compiled", "没有生成机器码"),
+    ("declare-string-failed", "声明字符串字面量失败: {error}"),
+    ("define-string-failed", "定义字符串字面量失败: {error}"),
+    ("unsupported-abi", "不支持的 abi: {abi}"),
+    ("type-not-found", "在此作用域中找不到类型 `{name}`"),
+    ("variable-not-in-data-map", "变量 `{name}` 不在数据表中"),
+    ("undefined-variable", "未定义的变量: `{name}`"),
+    ("field-not-found", "结构体 `{name}` 中没有字段 `{field}`"),
+    ("field-access-on-non-struct", "对非结构体类型 `{ty}` 访问字段"),
+    ("field-set-on-non-struct", "对非结构体类型 `{ty}` 设置字段"),
+    ("mismatched-types", "类型不匹配: 期望 `{expected}`, 实际为 `{found}`"),
+    ("assign-to-type", "不能对类型赋值: `{name}`"),
+    ("invalid-assign-target", "赋值目标必须是变量或字段"),
+    ("call-non-function", "调用了非函数类型 `{ty}`"),
+    ("verifier-errors", "IR 校验失败"),
+    ("jit-finalize-failed", "JIT 代码重定位失败: {error}"),
+    ("main-not-found", "没有可运行的 `main` 函数"),
+    ("main-bad-signature", "`main` 必须是 `main()` 或 `main(argc, argv)`, 实际为 `{signature}`"),
+    ("function-not-found", "没有定义名为 `{name}` 的函数"),
+    ("function-signature-mismatch", "Rust 类型与 `{name}` 的签名 `{expected}` 不一致"),
+    ("invalid-target", "无效的目标平台 `{target}`: {error}"),
+    ("unsupported-target", "不支持的目标平台 `{target}`: {error}"),
+    ("host-isa-unavailable", "不支持当前主机平台: {error}"),
+    ("target-cpu-native-cross", "交叉编译到 `{target}` 时不能使用 --target-cpu native"),
+    ("unsupported-target-cpu", "目标平台 `{target}` 不支持 CPU `{cpu}`: {error}"),
+    ("invalid-codegen-flag", "无效的 Cranelift 设置 `{key}={value}`: {error}"),
+    ("jit-cross-target", "JIT 只能为本机编译, 不能指定目标平台 `{target}`"),
+    ("emit-object-failed", "生成目标文件失败: {error}"),
+    ("create-object-builder-failed", "创建目标文件失败: {error}"),
+    // 链接
+    ("create-temp-dir-failed", "无法创建临时目录: {error}"),
+    ("linker-not-found", "无法运行链接器 `{linker}`: {error}"),
+    ("runtime-compile-failed", "为 `{target}` 编译运行时失败"),
+    ("link-failed", "链接失败 ({status})"),
+];
+
+pub fn message(key: &str) -> Option<&'static str> {
+    MESSAGES
+        .iter()
+        .find(|(it, _)| *it == key)
+        .map(|(_, message)| *message)
+}
@@ -2,4 +2,11 @@ pub mod value;
 pub mod traits;
 pub mod compiler;
 pub mod monomorphizer;
-pub mod args;
\ No newline at end of file
+pub mod optimizer;
+pub mod args;
+pub mod diagnostic;
+pub mod i18n;
+pub mod manifest;
+pub mod module;
+pub mod repl;
+pub mod session;
\ No newline at end of file
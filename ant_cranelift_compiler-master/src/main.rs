@@ -1,130 +1,394 @@
 mod args;
 mod compiler;
+mod diagnostic;
+mod i18n;
+mod manifest;
+mod module;
 mod monomorphizer;
+mod optimizer;
+mod repl;
+mod session;
 mod traits;
 mod value;
 
 use std::{
     cell::RefCell,
     fs,
-    path::PathBuf,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process,
     rc::Rc, sync::{Arc, Mutex},
 };
 
 use crate::{compiler::{
-    Compiler, compile_to_executable, create_target_isa, table::SymbolTable,
-}, monomorphizer::Monomorphizer};
-
-use ant_lexer::Lexer;
-use ant_parser::{Parser, error::display_err};
+    Compiler, compile_to_executable, compile_to_library, create_target_isa,
+    header::generate_header, jit::create_jit_isa, library_file_name, table::SymbolTable,
+}, diagnostic::{CompileError, SourceMap, Stage, codes::ErrorCode}, manifest::Manifest, module::{ModuleLoader, separate::compile_modules}, session::{CompileOptions, LinkMode, analyze_modules}, tr};
 
 use ant_type_checker::{
-    TypeChecker,
     table::TypeTable,
+    typed_ast::typed_node::TypedNode,
 };
 
-use clap::Parser as ClapParser;
+use crate::args::{Args, Command, Emit, ErrorFormat, parse_args};
 
-use crate::args::{Args, ARG};
+/// 读取源文件及其导入的模块, 并完成词法分析、语法分析、类型检查与单态化
+///
+/// 多个文件按顺序作为同一个程序分析
+fn analyze(
+    files: &[PathBuf],
+    sources: &mut SourceMap,
+) -> Result<(TypedNode, Arc<Mutex<TypeTable>>), CompileError> {
+    let modules = ModuleLoader::new(vec![]).load(files, sources)?;
 
-fn compile(arg: Args) {
-    unsafe { ARG = Some(arg.clone()) };
-    
-    let file_arc: Arc<str> = arg.file.clone().into();
-    let file = PathBuf::from(arg.file);
+    let type_table = Arc::new(Mutex::new(TypeTable::new().init()));
 
-    if !file.exists() {
-        panic!("file is not exists: {}", file.to_string_lossy())
-    }
+    let typed_program = analyze_modules(modules, type_table.clone())?;
 
-    let file_content = fs::read_to_string(&file).expect("read file error");
+    Ok((typed_program, type_table))
+}
 
-    let mut lexer = Lexer::new(file_content, file_arc.clone());
+/// 由命令行参数得到本次编译的选项
+fn compile_options(arg: &Args, file: &str) -> CompileOptions {
+    CompileOptions::new()
+        .script_mode(arg.script_mode)
+        .file_name(file)
+        .link_with(arg.link_with.iter().cloned())
+        .lib_paths(arg.lib_paths.iter().cloned())
+        .libs(arg.libs.iter().cloned())
+        .link_args(arg.link_args.iter().cloned())
+        .linker(arg.linker.clone())
+        .link_mode(match (arg.static_link, arg.dynamic) {
+            (true, _) => Some(LinkMode::Static),
+            (_, true) => Some(LinkMode::Dynamic),
+            _ => None,
+        })
+        .save_temps(arg.save_temps)
+        .incremental(arg.incremental.clone())
+        .jobs(arg.jobs.map(NonZeroUsize::get))
+        .opt_level(arg.opt_level.level())
+        .target(arg.target.clone())
+        .target_cpu(arg.target_cpu)
+        .codegen_flags(arg.codegen.iter().cloned())
+        .crate_type(arg.crate_type)
+        .pie(match (arg.pie, arg.no_pie) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        })
+}
 
-    let tokens = lexer.get_tokens();
+fn compile(arg: Args, sources: &mut SourceMap) -> Result<(), CompileError> {
+    let Some(file) = arg.file.clone() else {
+        return Err(CompileError::new(Stage::Io, tr!("no-input-file")));
+    };
 
-    if lexer.contains_error() {
-        lexer.print_errors();
-        println!();
-        panic!("lexer error")
-    }
+    let file_arc: Arc<str> = file.clone().into();
+    let file = PathBuf::from(file);
 
-    let mut parser = Parser::new(tokens);
+    let (typed_program, type_table) = analyze(std::slice::from_ref(&file), sources)?;
 
-    let program = match parser.parse_program() {
-        Ok(it) => it,
-        Err(err) => {
-            display_err(&err);
-            println!();
-            panic!("parser error")
-        }
-    };
+    let options = compile_options(&arg, &file_arc);
 
-    let type_table = Arc::new(Mutex::new(TypeTable::new().init()));
+    // 库的产物为库文件与 `<源文件名>.h` 头文件
+    let library = arg.emit == Emit::Exe && options.crate_type.is_library();
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy().to_string();
 
-    let mut checker = TypeChecker::new(type_table.clone());
+    let output_path = if let Some(it) = arg.output.clone() {
+        PathBuf::from(it)
+    } else if library {
+        let name = library_file_name(&stem, &options)
+            .map_err(|it| CompileError::new(Stage::Link, it))?;
 
-    let mut typed_program = match checker.check_node(program) {
-        Ok(it) => it,
-        Err(err) => {
-            println!("{err:#?}");
-            println!();
-            panic!("type checker error")
-        }
+        file.with_file_name(name)
+    } else {
+        file.with_extension(arg.emit.extension())
     };
 
-    let mut monomorphizer = Monomorphizer::new();
-    match monomorphizer.monomorphize(&mut typed_program) {
-        Ok(_) => (),
-        Err(it) => {
-            println!("{it}");
-            println!();
-            panic!("monomorphizer error")
-        }
+    if arg.emit == Emit::TypedAst {
+        let TypedNode::Program { statements, .. } = &typed_program;
+
+        let typed_ast = statements
+            .iter()
+            .map(|it| format!("{it}\n"))
+            .collect::<String>();
+
+        return write_output(&output_path, typed_ast.as_bytes());
+    }
+
+    if library {
+        let header = generate_header(&typed_program, &stem)
+            .map_err(|it| CompileError::new(Stage::Codegen, it))?;
+
+        write_output(&output_path.with_file_name(format!("{stem}.h")), header.as_bytes())?;
     }
 
+    codegen(arg.emit, typed_program, type_table, &options, &output_path, library)
+}
+
+/// 代码生成并按 `emit` 输出产物
+fn codegen(
+    emit: Emit,
+    typed_program: TypedNode,
+    type_table: Arc<Mutex<TypeTable>>,
+    options: &CompileOptions,
+    output_path: &Path,
+    library: bool,
+) -> Result<(), CompileError> {
+    let target_isa =
+        create_target_isa(options).map_err(|it| CompileError::new(Stage::Codegen, it))?;
+
     let compiler = Compiler::new(
-        create_target_isa(),
-        file_arc.clone(),
+        target_isa,
         Rc::new(RefCell::new(SymbolTable::new())),
-        type_table.clone(),
-    );
+        type_table,
+        options.clone(),
+    )
+    .map_err(|it| CompileError::new(Stage::Codegen, it))?
+    .dump_functions(emit == Emit::Clif, emit == Emit::Asm);
 
-    let code = match compiler.compile_program(typed_program) {
-        Ok(code) => code,
-        Err(err) => {
-            println!("{err}");
-            println!();
-            panic!("compiler error")
+    let program = compiler
+        .compile_program(typed_program)
+        .map_err(|it| CompileError::from_diagnostics(Stage::Codegen, it))?;
+
+    match emit {
+        Emit::Obj => write_output(output_path, &program.object),
+
+        Emit::Clif => {
+            let clif = program
+                .functions
+                .iter()
+                .filter_map(|it| Some(format!("; {}\n{}\n", it.name, it.clif.as_ref()?)))
+                .collect::<String>();
+
+            write_output(output_path, clif.as_bytes())
+        }
+
+        Emit::Asm => {
+            let asm = program
+                .functions
+                .iter()
+                .filter_map(|it| Some(format!("{}:\n{}\n", it.name, it.asm.as_ref()?)))
+                .collect::<String>();
+
+            write_output(output_path, asm.as_bytes())
+        }
+
+        Emit::Exe => {
+            create_output_dir(output_path)?;
+
+            if library {
+                compile_to_library(&program.object, output_path, options)
+            } else {
+                compile_to_executable(&[&program.object], output_path, options)
+            }
+            .map_err(|it| CompileError::new(Stage::Link, it))
+        }
+
+        // 已在代码生成之前输出
+        Emit::TypedAst => Ok(()),
+    }
+}
+
+fn create_output_dir(output_path: &Path) -> Result<(), CompileError> {
+    if let Some(parent) = output_path.parent().filter(|it| !it.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|it| CompileError::new(Stage::Io, it.to_string()))?;
+    }
+
+    Ok(())
+}
+
+fn write_output(output_path: &Path, content: &[u8]) -> Result<(), CompileError> {
+    create_output_dir(output_path)?;
+
+    fs::write(output_path, content).map_err(|it| {
+        CompileError::new(
+            Stage::Io,
+            tr!("write-output-failed", path = output_path.display(), error = it),
+        )
+    })
+}
+
+/// `antc build`: 按 `Ant.toml` 构建项目, 返回可执行文件的路径
+fn build(
+    arg: &Args,
+    release: bool,
+    manifest_path: Option<&str>,
+    sources: &mut SourceMap,
+) -> Result<PathBuf, CompileError> {
+    let manifest_path = match manifest_path {
+        Some(it) => PathBuf::from(it),
+        None => {
+            let cwd = std::env::current_dir()
+                .map_err(|it| CompileError::new(Stage::Io, it.to_string()))?;
+
+            Manifest::find(&cwd)
+                .ok_or_else(|| CompileError::new(Stage::Io, tr!("manifest-not-found")))?
         }
     };
 
-    #[cfg(windows)]
-    let output_file_stem = ".exe";
+    let manifest =
+        Manifest::load(&manifest_path).map_err(|it| CompileError::new(Stage::Io, it))?;
 
-    #[cfg(not(windows))]
-    let output_file_stem = "";
+    let modules = ModuleLoader::new(vec![manifest.root.clone()])
+        .load(&manifest.source_files(), sources)?;
 
-    let output_path = if let Some(it) = arg.output {
-        PathBuf::from(it)
-    } else {
-        file.parent().unwrap().join(PathBuf::from(
-            file.file_stem().unwrap().to_string_lossy().to_string() + output_file_stem,
-        ))
+    // 命令行的全局选项追加在清单之后, 优先生效
+    let options = manifest
+        .compile_options(release)
+        .map_err(|it| CompileError::new(Stage::Io, it))?
+        .target_cpu(arg.target_cpu)
+        .codegen_flags(arg.codegen.iter().cloned())
+        .incremental(Some(manifest.cache_dir()))
+        .jobs(arg.jobs.map(NonZeroUsize::get));
+
+    // 每个模块分别编译, 未变化的模块复用上次的目标文件
+    let compiled = compile_modules(
+        modules,
+        &manifest.package.name,
+        &manifest.deps_dir(release),
+        &options,
+    )?;
+
+    let output_path = manifest.output_path(release);
+    create_output_dir(&output_path)?;
+
+    let objects = compiled.objects.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+    compile_to_executable(&objects, &output_path, &options)
+        .map_err(|it| CompileError::new(Stage::Link, it))?;
+
+    Ok(output_path)
+}
+
+/// `antc run`: 指定文件时 JIT 编译后直接执行 `main`, 否则构建项目后运行可执行文件
+///
+/// 返回程序的退出码
+fn run(
+    arg: &Args,
+    file: Option<&str>,
+    release: bool,
+    manifest_path: Option<&str>,
+    args: &[String],
+    sources: &mut SourceMap,
+) -> Result<i32, CompileError> {
+    let Some(file) = file else {
+        let exe = build(arg, release, manifest_path, sources)?;
+
+        let status = process::Command::new(&exe).args(args).status().map_err(|it| {
+            CompileError::new(
+                Stage::Io,
+                tr!("run-program-failed", path = exe.display(), error = it),
+            )
+        })?;
+
+        return Ok(status.code().unwrap_or(1));
     };
 
-    if !output_path.exists() {
-        fs::create_dir_all(&output_path.parent().unwrap()).unwrap()
+    let (typed_program, type_table) = analyze(&[PathBuf::from(file)], sources)?;
+
+    let options = compile_options(arg, file);
+
+    let target_isa =
+        create_jit_isa(&options).map_err(|it| CompileError::new(Stage::Codegen, it))?;
+
+    let compiler = Compiler::new_jit(
+        target_isa,
+        Rc::new(RefCell::new(SymbolTable::new())),
+        type_table,
+        options,
+    )
+    .map_err(|it| CompileError::new(Stage::Codegen, it))?;
+
+    let program = compiler
+        .compile_jit(typed_program)
+        .map_err(|it| CompileError::from_diagnostics(Stage::Codegen, it))?;
+
+    // argv[0] 为源文件路径
+    let argv = std::iter::once(file.to_string())
+        .chain(args.iter().cloned())
+        .collect::<Vec<_>>();
+
+    program
+        .run_main(&argv)
+        .map_err(|it| CompileError::new(Stage::Codegen, it))
+}
+
+fn explain(code: &str) {
+    match code.parse::<ErrorCode>() {
+        Ok(code) => println!("{}", code.explanation()),
+        Err(err) => {
+            eprintln!("error: {err}");
+            process::exit(1);
+        }
     }
+}
 
-    match compile_to_executable(&code, &output_path) {
-        Ok(_) => (),
-        Err(it) => println!("{it}"),
+/// 输出诊断信息并以对应阶段的退出码退出
+fn report(err: CompileError, error_format: ErrorFormat, sources: &SourceMap) -> ! {
+    match error_format {
+        ErrorFormat::Human => {
+            eprintln!("{}", err.render(sources));
+            eprintln!("{}", err.summary());
+        }
+        ErrorFormat::Json => eprintln!("{}", err.render_json()),
     }
+
+    process::exit(err.exit_code());
 }
 
 fn main() {
-    let args = Args::parse();
+    let args = parse_args();
+
+    if let Some(code) = &args.explain {
+        explain(code);
+        return;
+    }
 
-    compile(args);
+    let error_format = args.error_format;
+
+    let mut sources = SourceMap::new();
+
+    match &args.command {
+        Some(Command::Run {
+            file,
+            release,
+            manifest_path,
+            args: program_args,
+        }) => {
+            match run(
+                &args,
+                file.as_deref(),
+                *release,
+                manifest_path.as_deref(),
+                program_args,
+                &mut sources,
+            ) {
+                Ok(code) => process::exit(code),
+                Err(err) => report(err, error_format, &sources),
+            }
+        }
+        Some(Command::Build {
+            release,
+            manifest_path,
+        }) => {
+            if let Err(err) = build(&args, *release, manifest_path.as_deref(), &mut sources) {
+                report(err, error_format, &sources);
+            }
+
+            return;
+        }
+        Some(Command::Repl) => {
+            if let Err(err) = repl::run_repl() {
+                report(err, error_format, &sources);
+            }
+
+            return;
+        }
+        None => {}
+    }
+
+    if let Err(err) = compile(args, &mut sources) {
+        report(err, error_format, &sources);
+    }
 }
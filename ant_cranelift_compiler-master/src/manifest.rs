@@ -0,0 +1,283 @@
+//! 项目清单 `Ant.toml`, 供 `antc build` 与 `antc run` 使用
+//!
+//! ```toml
+//! [package]
+//! name = "app"
+//! entry = "src/main.ant"
+//! sources = ["src/util.ant"]
+//!
+//! [link]
+//! libs = ["m"]
+//! lib-paths = ["native"]
+//!
+//! [profile.release]
+//! opt-level = 3
+//! codegen = ["preserve_frame_pointers=true"]
+//! ```
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    args::{Emit, OptLevelArg, parse_codegen_flag},
+    diagnostic::Diagnostic,
+    session::{CompileOptions, OptLevel},
+    tr,
+};
+
+pub const MANIFEST_NAME: &str = "Ant.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Manifest {
+    pub package: Package,
+    #[serde(default)]
+    pub link: Link,
+    #[serde(default)]
+    pub profile: Profiles,
+
+    /// `Ant.toml` 所在目录, 清单中的相对路径都相对于它
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Package {
+    /// 输出文件名
+    pub name: String,
+    /// 入口文件 (包含 `main`)
+    #[serde(default = "default_entry")]
+    pub entry: PathBuf,
+    /// 其余源文件, 按顺序放在入口文件之前
+    #[serde(default)]
+    pub sources: Vec<PathBuf>,
+    #[serde(default)]
+    pub script_mode: bool,
+}
+
+/// 与命令行的 `-L`、`-l`、`--link`、`--link-arg` 对应
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Link {
+    #[serde(default)]
+    pub lib_paths: Vec<String>,
+    #[serde(default)]
+    pub libs: Vec<String>,
+    #[serde(default)]
+    pub link_with: Vec<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profiles {
+    pub debug: Option<Profile>,
+    pub release: Option<Profile>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    pub opt_level: Option<OptLevelValue>,
+    /// 与命令行的 `-C key=value` 相同
+    #[serde(default)]
+    pub codegen: Vec<String>,
+}
+
+/// `opt-level = 3` 或 `opt-level = "s"`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OptLevelValue {
+    Int(u8),
+    Str(String),
+}
+
+fn default_entry() -> PathBuf {
+    PathBuf::from("src/main.ant")
+}
+
+impl Manifest {
+    /// 从 `dir` 开始向上查找 `Ant.toml`
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|it| it.join(MANIFEST_NAME))
+            .find(|it| it.is_file())
+    }
+
+    pub fn load(path: &Path) -> Result<Manifest, Diagnostic> {
+        let content =
+            fs::read_to_string(path).map_err(|e| tr!("read-file-error", error = e))?;
+
+        let mut manifest = toml::from_str::<Manifest>(&content).map_err(|e| {
+            Diagnostic::error(tr!("invalid-manifest", path = path.display()))
+                .with_note(e.to_string())
+        })?;
+
+        manifest.root = path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| ".".into());
+
+        Ok(manifest)
+    }
+
+    /// 参与编译的源文件: `sources` 在前, 入口文件在最后
+    pub fn source_files(&self) -> Vec<PathBuf> {
+        self.package
+            .sources
+            .iter()
+            .chain(std::iter::once(&self.package.entry))
+            .map(|it| self.root.join(it))
+            .collect()
+    }
+
+    pub fn profile_name(release: bool) -> &'static str {
+        if release { "release" } else { "debug" }
+    }
+
+    /// `target/<profile>/<name>` (Windows 上为 `<name>.exe`)
+    pub fn output_path(&self, release: bool) -> PathBuf {
+        // 包名中可以有 `.` (如 `app.v2`), 后缀直接追加在后面, 不能用 with_extension 替换
+        let mut file_name = self.package.name.clone();
+
+        if !Emit::Exe.extension().is_empty() {
+            file_name.push('.');
+            file_name.push_str(Emit::Exe.extension());
+        }
+
+        self.root
+            .join("target")
+            .join(Self::profile_name(release))
+            .join(file_name)
+    }
+
+    /// 分别编译的各模块的目标文件与接口文件: `target/<profile>/deps`
+    pub fn deps_dir(&self, release: bool) -> PathBuf {
+        self.root
+            .join("target")
+            .join(Self::profile_name(release))
+            .join("deps")
+    }
+
+    /// 增量编译缓存: `target/antc-cache`, 各 profile 共用 (缓存键中包含编译设置)
+    pub fn cache_dir(&self) -> PathBuf {
+        self.root.join("target").join("antc-cache")
+    }
+
+    /// 由清单得到编译选项, 未配置的 profile 使用默认值 (debug: O0, release: O3)
+    pub fn compile_options(&self, release: bool) -> Result<CompileOptions, Diagnostic> {
+        let (profile, default_level) = if release {
+            (self.profile.release.clone(), OptLevel::O3)
+        } else {
+            (self.profile.debug.clone(), OptLevel::O0)
+        };
+
+        let profile = profile.unwrap_or_default();
+
+        let opt_level = match &profile.opt_level {
+            None => default_level,
+            Some(OptLevelValue::Int(level)) => OptLevelArg::from_str(&level.to_string())?.level(),
+            Some(OptLevelValue::Str(level)) => OptLevelArg::from_str(level)?.level(),
+        };
+
+        let codegen_flags = profile
+            .codegen
+            .iter()
+            .map(|it| parse_codegen_flag(it))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // 清单中的相对路径相对于清单所在目录
+        let relative = |it: &String| self.root.join(it).to_string_lossy().to_string();
+
+        Ok(CompileOptions::new()
+            .script_mode(self.package.script_mode)
+            .file_name(self.root.join(&self.package.entry).to_string_lossy().to_string())
+            .opt_level(opt_level)
+            .codegen_flags(codegen_flags)
+            .lib_paths(self.link.lib_paths.iter().map(relative))
+            .libs(self.link.libs.iter().cloned())
+            .link_with(self.link.link_with.iter().map(relative))
+            .link_args(self.link.args.iter().cloned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{manifest::Manifest, session::OptLevel};
+
+    #[test]
+    fn load_manifest_and_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("src").join("nested");
+        fs::create_dir_all(&nested).unwrap();
+
+        fs::write(
+            dir.path().join("Ant.toml"),
+            r#"
+            [package]
+            name = "app"
+            sources = ["src/util.ant"]
+
+            [link]
+            libs = ["m"]
+            lib-paths = ["native"]
+
+            [profile.release]
+            opt-level = "s"
+            codegen = ["preserve_frame_pointers=true"]
+            "#,
+        )
+        .unwrap();
+
+        let path = Manifest::find(&nested).unwrap();
+        let manifest = Manifest::load(&path).unwrap();
+
+        assert_eq!(
+            manifest.source_files(),
+            [dir.path().join("src/util.ant"), dir.path().join("src/main.ant")]
+        );
+        assert_eq!(
+            manifest.output_path(true).parent().unwrap(),
+            dir.path().join("target").join("release")
+        );
+
+        let debug = manifest.compile_options(false).unwrap();
+        assert_eq!(debug.opt_level, OptLevel::O0);
+        assert_eq!(debug.libs, ["m"]);
+        assert_eq!(debug.lib_paths, [dir.path().join("native").to_string_lossy()]);
+
+        let release = manifest.compile_options(true).unwrap();
+        assert_eq!(release.opt_level, OptLevel::Size);
+        assert_eq!(
+            release.codegen_flags,
+            [("preserve_frame_pointers".to_string(), "true".to_string())]
+        );
+
+        fs::write(&path, "[package]\nname = 1").unwrap();
+        assert!(Manifest::load(&path).is_err());
+    }
+
+    #[test]
+    fn output_path_keeps_dotted_package_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Ant.toml");
+        fs::write(&path, "[package]\nname = \"app.v2\"\n").unwrap();
+
+        let manifest = Manifest::load(&path).unwrap();
+        let expected = if cfg!(windows) { "app.v2.exe" } else { "app.v2" };
+
+        assert_eq!(
+            manifest.output_path(false),
+            dir.path().join("target").join("debug").join(expected)
+        );
+    }
+}
@@ -0,0 +1,332 @@
+//! 模块的接口文件 (`.anti`)
+//!
+//! 记录模块导出的函数签名、常量与结构体布局, 依赖它的模块读取接口生成的声明,
+//! 而不必重新检查它的源码
+
+use std::{collections::HashMap, fmt::Write, fs, path::Path, sync::Arc};
+
+use ant_ast::expr::IntValue;
+use ant_type_checker::{
+    ty::{IntTy, Ty},
+    typed_ast::{GetType, typed_expr::TypedExpression, typed_stmt::TypedStatement},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compiler::table::{StructLayout, SymbolTable, SymbolTy},
+    diagnostic::Diagnostic,
+    module::Module,
+    tr,
+    traits::LiteralExprToConst,
+    value::ConstVal,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interface {
+    pub namespace: String,
+    /// 模块源码、依赖的接口与编译设置的哈希, 不同时接口与目标文件都需要重新生成
+    pub hash: u64,
+    /// 依赖方能否只读取接口; 含泛型函数、`impl` 或无法写进声明的类型时为 false
+    pub complete: bool,
+    pub functions: Vec<FunctionInterface>,
+    pub consts: Vec<ConstInterface>,
+    pub structs: Vec<StructInterface>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionInterface {
+    /// 带命名空间的名字, 同时也是目标文件中的符号名
+    pub name: String,
+    /// 参数名与类型
+    pub params: Vec<(String, String)>,
+    /// 无返回值时为 None
+    pub ret: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConstInterface {
+    pub name: String,
+    /// 常量的值, 以 Ant 字面量表示
+    pub value: String,
+}
+
+/// 与 [`StructLayout`] 对应, 字段类型以类型名表示
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StructInterface {
+    pub name: String,
+    pub fields: Vec<(String, String)>,
+    pub offsets: Vec<u32>,
+    pub size: u32,
+    pub align: u32,
+}
+
+impl Interface {
+    /// 由模块的顶层语句得到函数与常量, 结构体由 [`Interface::add_structs`] 补充
+    ///
+    /// 只收集 `namespace` 下的顶层定义, 程序中其他模块的声明不属于该模块
+    pub fn new(namespace: &str, hash: u64, statements: &[TypedStatement]) -> Self {
+        let mut interface = Self {
+            namespace: namespace.to_string(),
+            hash,
+            complete: true,
+            functions: vec![],
+            consts: vec![],
+            structs: vec![],
+        };
+
+        for stmt in statements {
+            match stmt {
+                TypedStatement::ExpressionStatement(TypedExpression::Function {
+                    name: Some(name),
+                    params,
+                    generics_params,
+                    block,
+                    ..
+                }) if own(namespace, &name.value) => {
+                    // 泛型函数在使用处单态化, 无法只凭签名调用
+                    if !generics_params.is_empty() {
+                        interface.complete = false;
+                        continue;
+                    }
+
+                    let params = params
+                        .iter()
+                        .map(|param| match &**param {
+                            TypedExpression::TypeHint(name, _, ty) => {
+                                Some((name.value.to_string(), type_name(ty)?))
+                            }
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<_>>>();
+
+                    let ret = match block.get_type() {
+                        Ty::Unit => Some(None),
+                        ty => type_name(&ty).map(Some),
+                    };
+
+                    match (params, ret) {
+                        (Some(params), Some(ret)) => interface.functions.push(FunctionInterface {
+                            name: name.value.to_string(),
+                            params,
+                            ret,
+                        }),
+                        _ => interface.complete = false,
+                    }
+                }
+
+                TypedStatement::Const { name, value, .. } if own(namespace, &name.value) => {
+                    match value.to_const().and_then(|it| const_literal(&it)) {
+                        Some(value) => interface.consts.push(ConstInterface {
+                            name: name.value.to_string(),
+                            value,
+                        }),
+                        None => interface.complete = false,
+                    }
+                }
+
+                TypedStatement::Impl { .. } => interface.complete = false,
+
+                _ => {}
+            }
+        }
+
+        interface
+    }
+
+    /// 从代码生成后的符号表中收集结构体布局
+    pub fn add_structs(&mut self, table: &SymbolTable) {
+        let mut layouts = table
+            .map
+            .values()
+            .filter_map(|it| match &it.symbol_ty {
+                SymbolTy::Struct(layout) if own(&self.namespace, &layout.name) => Some(layout),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        // 符号表无序, 按名字排序保证接口文件稳定
+        layouts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for layout in layouts {
+            match StructInterface::new(layout) {
+                Some(it) => self.structs.push(it),
+                None => self.complete = false,
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> Option<Self> {
+        serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Diagnostic> {
+        let content = serde_json::to_string_pretty(self).unwrap();
+
+        fs::write(path, content)
+            .map_err(|e| tr!("write-output-failed", path = path.display(), error = e).into())
+    }
+
+    /// 依赖方看到的部分的哈希: 只修改函数体时不变, 依赖方也就不需要重新编译
+    pub fn public_hash(&self) -> u64 {
+        let public = Self {
+            hash: 0,
+            ..self.clone()
+        };
+
+        super::separate::hash(&serde_json::to_string(&public).unwrap())
+    }
+
+    /// 由接口生成声明: 函数为 `extern "C"` 函数, 常量与结构体照原样声明
+    ///
+    /// `file` 为接口文件的路径, 声明中的错误会指向它
+    pub fn stub(&self, file: Arc<str>) -> Module {
+        let mut aliases = Aliases::default();
+        let mut source = String::new();
+
+        for it in &self.structs {
+            let fields = it
+                .fields
+                .iter()
+                .filter(|(name, _)| name != "__ref_count__")
+                .map(|(name, ty)| format!("{name}: {}", aliases.qualify(ty)))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            writeln!(source, "struct {} {{ {fields} }}", aliases.qualify(&it.name)).unwrap();
+        }
+
+        for it in &self.consts {
+            writeln!(source, "const {} = {};", aliases.qualify(&it.name), it.value).unwrap();
+        }
+
+        for it in &self.functions {
+            let params = it
+                .params
+                .iter()
+                .map(|(name, ty)| format!("{name}: {}", aliases.qualify(ty)))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            write!(source, "extern \"C\" func {}({params})", aliases.qualify(&it.name)).unwrap();
+
+            if let Some(ret) = &it.ret {
+                write!(source, " -> {}", aliases.qualify(ret)).unwrap();
+            }
+
+            writeln!(source, ";").unwrap();
+        }
+
+        Module {
+            namespace: None,
+            file,
+            source,
+            imports: aliases.imports,
+        }
+    }
+}
+
+impl StructInterface {
+    fn new(layout: &StructLayout) -> Option<Self> {
+        Some(Self {
+            name: layout.name.to_string(),
+            fields: layout
+                .fields
+                .iter()
+                .map(|(name, ty)| Some((name.to_string(), type_name(ty)?)))
+                .collect::<Option<Vec<_>>>()?,
+            offsets: layout.offsets.clone(),
+            size: layout.size,
+            align: layout.align,
+        })
+    }
+}
+
+/// 带命名空间的名字无法直接写进源码, 写作 `别名.名字`, 由 [`Module::qualify_tokens`] 合并回来
+#[derive(Default)]
+struct Aliases {
+    imports: HashMap<Arc<str>, Option<Arc<str>>>,
+}
+
+impl Aliases {
+    fn qualify(&mut self, name: &str) -> String {
+        let Some((namespace, member)) = name.rsplit_once("::") else {
+            return name.to_string();
+        };
+
+        let alias = match self
+            .imports
+            .iter()
+            .find(|(_, it)| it.as_deref() == Some(namespace))
+        {
+            Some((alias, _)) => alias.clone(),
+            None => {
+                let alias: Arc<str> = format!("__module{}", self.imports.len()).into();
+                self.imports.insert(alias.clone(), Some(namespace.into()));
+                alias
+            }
+        };
+
+        format!("{alias}.{member}")
+    }
+}
+
+/// `name` 是否直接定义在 `namespace` 中
+fn own(namespace: &str, name: &str) -> bool {
+    name.strip_prefix(namespace)
+        .and_then(|it| it.strip_prefix("::"))
+        .is_some_and(|it| !it.contains("::"))
+}
+
+/// 类型在源码中的写法, 不能写进声明的类型 (函数、泛型等) 为 None
+fn type_name(ty: &Ty) -> Option<String> {
+    Some(match ty {
+        Ty::IntTy(ty) => int_type_name(ty).to_string(),
+        Ty::Bool => "bool".to_string(),
+        Ty::Str => "str".to_string(),
+        Ty::Struct { name, .. } => name.to_string(),
+        _ => None?,
+    })
+}
+
+fn int_type_name(ty: &IntTy) -> &'static str {
+    match ty {
+        IntTy::USize => "usize",
+        IntTy::ISize => "isize",
+        IntTy::I64 => "i64",
+        IntTy::I32 => "i32",
+        IntTy::I16 => "i16",
+        IntTy::I8 => "i8",
+        IntTy::U64 => "u64",
+        IntTy::U32 => "u32",
+        IntTy::U16 => "u16",
+        IntTy::U8 => "u8",
+    }
+}
+
+/// 常量的字面量写法; 负数需要写成表达式, 不是字面量, 为 None
+fn const_literal(value: &ConstVal) -> Option<String> {
+    Some(match value {
+        ConstVal::Int(value) => {
+            let (value, ty) = match value {
+                IntValue::I64(it) => (it.to_string(), IntTy::I64),
+                IntValue::I32(it) => (it.to_string(), IntTy::I32),
+                IntValue::I16(it) => (it.to_string(), IntTy::I16),
+                IntValue::I8(it) => (it.to_string(), IntTy::I8),
+                IntValue::ISize(it) => (it.to_string(), IntTy::ISize),
+                IntValue::U64(it) => (it.to_string(), IntTy::U64),
+                IntValue::U32(it) => (it.to_string(), IntTy::U32),
+                IntValue::U16(it) => (it.to_string(), IntTy::U16),
+                IntValue::U8(it) => (it.to_string(), IntTy::U8),
+                IntValue::USize(it) => (it.to_string(), IntTy::USize),
+            };
+
+            if value.starts_with('-') {
+                return None;
+            }
+
+            format!("{value}{}", int_type_name(&ty))
+        }
+        ConstVal::Str(value) => format!("{value:?}"),
+        ConstVal::Bool(value) => value.to_string(),
+    })
+}
@@ -0,0 +1,691 @@
+//! 多文件程序: `import` 的解析、模块的加载顺序与模块命名空间
+//!
+//! ```ant
+//! import "util.ant"      // 相对于当前文件, 其次相对于搜索路径 (`Ant.toml` 所在目录)
+//! import math.ops        // math/ops.ant
+//!
+//! func main() -> i32 {
+//!     util.answer() + ops.add(1i32, 2i32)
+//! }
+//! ```
+//!
+//! 被导入模块的顶层 `func`、`struct` 与 `const` 以 `模块::名字` 命名 (见 [`mangle`]),
+//! 其他模块通过 `别名.名字` 访问; 根模块 (入口文件与 `Ant.toml` 中的 `sources`) 不加前缀
+
+pub mod interface;
+pub mod separate;
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+};
+
+use ant_lexer::Lexer;
+use ant_token::{token::Token, token_type::TokenType};
+
+use crate::{
+    compiler::table::{SymbolTable, mangle},
+    diagnostic::{CompileError, Diagnostic, SourceMap, Span, Stage, codes::ErrorCode},
+    tr,
+};
+
+/// 一个源文件
+#[derive(Debug, Clone)]
+pub struct Module {
+    /// 模块的命名空间 (如 `math::ops`), 根模块为 None
+    pub namespace: Option<Arc<str>>,
+    pub file: Arc<str>,
+    pub source: String,
+    /// 别名 -> 被导入模块的命名空间
+    pub imports: HashMap<Arc<str>, Option<Arc<str>>>,
+}
+
+impl Module {
+    /// 没有导入其他模块的根模块
+    pub fn root(file: Arc<str>, source: String) -> Self {
+        Self {
+            namespace: None,
+            file,
+            source,
+            imports: HashMap::new(),
+        }
+    }
+
+    /// 去掉模块的 `import` 语句, 并为模块的 token 加上命名空间
+    ///
+    /// 1. 顶层定义及其在本模块中的引用改为 `模块::名字`
+    /// 2. `别名.名字` 合并为被导入模块中的 `模块::名字`
+    ///
+    /// 名字按作用域在 [`SymbolTable`] 中查找: 顶层定义登记在模块的表中, 由 `renamed_symbols`
+    /// 指向带命名空间的名字; 每层大括号是一层内部的表, 其中的参数与 `let` 遮蔽同名的顶层定义
+    pub fn qualify_tokens(&self, tokens: Vec<Token>) -> Result<Vec<Token>, Diagnostic> {
+        let (tokens, imports) = parse_imports(tokens)?;
+
+        // 根模块由 ModuleLoader 以外的途径创建时, 其中的 import 没有被解析
+        if let Some(import) = imports
+            .iter()
+            .find(|it| !self.imports.contains_key(&it.alias))
+        {
+            return Err(import.unresolved());
+        }
+
+        if self.namespace.is_none() && self.imports.is_empty() {
+            return Ok(tokens);
+        }
+
+        let module_table = Rc::new(RefCell::new(SymbolTable::new()));
+
+        for name in top_level_definitions(&tokens) {
+            let mut table = module_table.borrow_mut();
+
+            match &self.namespace {
+                Some(namespace) => {
+                    let mangled = mangle(namespace, &name);
+                    table.define(&mangled);
+                    table.renamed_symbols.insert(name.into(), mangled.into());
+                }
+                None => {
+                    table.define(&name);
+                }
+            }
+        }
+
+        let mut scopes = vec![module_table];
+        let mut qualified: Vec<Token> = Vec::with_capacity(tokens.len());
+
+        // 每层大括号是否为结构体定义或 `new` 的字段列表, 其中的字段名不改名
+        let mut field_blocks = vec![];
+        let mut pending_field_block = false;
+
+        // 函数的参数列表: 参数在函数体的大括号中定义
+        let mut after_func = false;
+        let mut paren_depth = 0usize;
+        let mut params_depth = None;
+        let mut pending_params: Vec<Arc<str>> = vec![];
+
+        // `let` 定义的名字在语句结束后才可见, 记录所在的作用域层数
+        let mut pending_lets: Vec<(usize, Arc<str>)> = vec![];
+
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let token = &tokens[i];
+            let next = tokens.get(i + 1).map(|it| it.value.as_ref());
+            let prev = qualified.last().map(|it| it.value.as_ref());
+
+            match token.value.as_ref() {
+                "struct" | "new" => pending_field_block = true,
+                "func" => after_func = true,
+                "(" => {
+                    paren_depth += 1;
+
+                    if std::mem::take(&mut after_func) {
+                        params_depth = Some(paren_depth);
+                    }
+                }
+                ")" => {
+                    if params_depth == Some(paren_depth) {
+                        params_depth = None;
+                    }
+
+                    paren_depth = paren_depth.saturating_sub(1);
+                }
+                "{" => {
+                    field_blocks.push(std::mem::take(&mut pending_field_block));
+
+                    let mut scope = SymbolTable::from_outer(scopes.last().unwrap().clone());
+                    for param in pending_params.drain(..) {
+                        scope.define(&param);
+                    }
+
+                    scopes.push(Rc::new(RefCell::new(scope)));
+                }
+                "}" => {
+                    field_blocks.pop();
+
+                    if scopes.len() > 1 {
+                        scopes.pop();
+                    }
+
+                    pending_lets.retain(|(depth, _)| *depth <= scopes.len());
+                }
+                ";" => {
+                    // 没有函数体的声明 (`extern` 函数)
+                    pending_params.clear();
+
+                    let depth = scopes.len();
+                    let mut scope = scopes.last().unwrap().borrow_mut();
+
+                    pending_lets.retain(|(it, name)| {
+                        if *it != depth {
+                            return true;
+                        }
+
+                        scope.define(name);
+                        false
+                    });
+                }
+                _ => {}
+            }
+
+            let after_dot = prev == Some(".");
+            let is_field_name = field_blocks.last() == Some(&true) && next == Some(":");
+
+            if token.token_type != TokenType::Ident || after_dot || is_field_name {
+                qualified.push(token.clone());
+                i += 1;
+                continue;
+            }
+
+            let is_param = params_depth == Some(paren_depth) && next == Some(":");
+            let is_let = prev == Some("let");
+
+            // 函数内部定义的函数与方法不属于模块的顶层
+            let is_local_func = prev == Some("func") && scopes.len() > 1;
+
+            if is_param || is_let || is_local_func {
+                if is_param {
+                    pending_params.push(token.value.clone());
+                } else if is_let {
+                    pending_lets.push((scopes.len(), token.value.clone()));
+                }
+
+                qualified.push(token.clone());
+                i += 1;
+                continue;
+            }
+
+            let symbol = scopes.last().unwrap().borrow_mut().get(&token.value);
+
+            // `别名.名字`, 别名没有被同名的变量或定义遮蔽
+            if symbol.is_none()
+                && let Some(namespace) = self.imports.get(&token.value)
+                && next == Some(".")
+                && let Some(member) = tokens.get(i + 2)
+                && member.token_type == TokenType::Ident
+            {
+                let mut merged = token.clone();
+                merged.value = match namespace {
+                    Some(namespace) => mangle(namespace, &member.value).into(),
+                    None => member.value.clone(),
+                };
+
+                qualified.push(merged);
+                i += 3;
+                continue;
+            }
+
+            let mut token = token.clone();
+
+            if let Some(symbol) = symbol {
+                token.value = symbol.name.as_ref().into();
+            }
+
+            qualified.push(token);
+            i += 1;
+        }
+
+        Ok(qualified)
+    }
+}
+
+/// 顶层的 `func`、`struct` 与 `const` 定义的名字 (`extern` 函数除外, 它们使用 C 的符号名)
+fn top_level_definitions(tokens: &[Token]) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let mut depth = 0usize;
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token.value.as_ref() {
+            "{" => depth += 1,
+            "}" => depth = depth.saturating_sub(1),
+            "func" | "struct" | "const" if depth == 0 => {
+                let is_extern = tokens[i.saturating_sub(2)..i]
+                    .iter()
+                    .any(|it| it.value.as_ref() == "extern");
+
+                if let Some(name) = tokens.get(i + 1)
+                    && name.token_type == TokenType::Ident
+                    && !is_extern
+                {
+                    names.insert(name.value.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    names
+}
+
+/// 一条 `import`
+#[derive(Debug, Clone)]
+struct Import {
+    /// 相对路径 (`foo/bar.ant`)
+    path: PathBuf,
+    /// 访问时使用的名字, 即文件名去掉后缀
+    alias: Arc<str>,
+    span: Option<Span>,
+}
+
+impl Import {
+    fn unresolved(&self) -> Diagnostic {
+        Diagnostic::error(tr!("unresolved-import", path = self.path.display()))
+            .with_code(ErrorCode::E0011)
+            .with_span(self.span.clone())
+    }
+}
+
+/// 按依赖顺序加载模块
+pub struct ModuleLoader {
+    /// 在导入方所在目录找不到时依次查找的目录
+    search_paths: Vec<PathBuf>,
+    modules: Vec<Module>,
+    /// 已加载的文件 -> 命名空间
+    loaded: HashMap<PathBuf, Option<Arc<str>>>,
+    /// 正在加载的文件, 用于发现循环导入
+    stack: Vec<PathBuf>,
+}
+
+impl ModuleLoader {
+    pub fn new(search_paths: Vec<PathBuf>) -> Self {
+        Self {
+            search_paths,
+            modules: vec![],
+            loaded: HashMap::new(),
+            stack: vec![],
+        }
+    }
+
+    /// 加载根文件及其导入的模块, 依赖排在导入方之前
+    ///
+    /// 读取的源码会加入 `sources`, 用于渲染诊断信息
+    pub fn load(
+        mut self,
+        roots: &[PathBuf],
+        sources: &mut SourceMap,
+    ) -> Result<Vec<Module>, CompileError> {
+        for root in roots {
+            if !root.exists() {
+                return Err(CompileError::new(
+                    Stage::Io,
+                    tr!("file-not-exists", path = root.to_string_lossy()),
+                ));
+            }
+
+            self.visit(root, None, sources)?;
+        }
+
+        Ok(self.modules)
+    }
+
+    fn visit(
+        &mut self,
+        path: &Path,
+        namespace: Option<Arc<str>>,
+        sources: &mut SourceMap,
+    ) -> Result<(), CompileError> {
+        let key = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+
+        if self.loaded.contains_key(&key) {
+            return Ok(());
+        }
+
+        let file: Arc<str> = path.to_string_lossy().to_string().into();
+
+        let content = fs::read_to_string(path)
+            .map_err(|it| CompileError::new(Stage::Io, tr!("read-file-error", error = it)))?;
+
+        sources.add(file.clone(), content.as_str().into());
+
+        // 词法错误留到分析模块时报告
+        let tokens = Lexer::new(content.clone(), file.clone()).get_tokens();
+        let (_, imports) =
+            parse_imports(tokens).map_err(|it| CompileError::new(Stage::Import, it))?;
+
+        self.stack.push(key.clone());
+
+        let mut aliases: HashMap<Arc<str>, Option<Arc<str>>> = HashMap::new();
+
+        for import in imports {
+            let dir = path.parent().unwrap_or(Path::new("."));
+
+            let Some(resolved) = std::iter::once(dir)
+                .chain(self.search_paths.iter().map(PathBuf::as_path))
+                .map(|base| base.join(&import.path))
+                .find(|it| it.is_file())
+            else {
+                return Err(CompileError::new(Stage::Import, import.unresolved()));
+            };
+
+            let resolved_key = fs::canonicalize(&resolved).unwrap_or_else(|_| resolved.clone());
+
+            if let Some(start) = self.stack.iter().position(|it| *it == resolved_key) {
+                let cycle = self.stack[start..]
+                    .iter()
+                    .chain(std::iter::once(&resolved_key))
+                    .map(|it| it.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+
+                return Err(CompileError::new(
+                    Stage::Import,
+                    Diagnostic::error(tr!("import-cycle", path = import.path.display()))
+                        .with_code(ErrorCode::E0010)
+                        .with_span(import.span)
+                        .with_note(cycle),
+                ));
+            }
+
+            let namespace = match self.loaded.get(&resolved_key) {
+                Some(it) => it.clone(),
+                None => {
+                    let namespace = self.namespace_for(&import.path);
+                    self.visit(&resolved, Some(namespace.clone()), sources)?;
+                    Some(namespace)
+                }
+            };
+
+            // 同一个别名只能指向一个模块
+            if let Some(existing) = aliases.get(&import.alias)
+                && *existing != namespace
+            {
+                return Err(CompileError::new(
+                    Stage::Import,
+                    Diagnostic::error(tr!("duplicate-import-alias", alias = import.alias))
+                        .with_code(ErrorCode::E0012)
+                        .with_span(import.span),
+                ));
+            }
+
+            aliases.insert(import.alias, namespace);
+        }
+
+        self.stack.pop();
+        self.loaded.insert(key, namespace.clone());
+
+        self.modules.push(Module {
+            namespace,
+            file,
+            source: content,
+            imports: aliases,
+        });
+
+        Ok(())
+    }
+
+    /// 由导入路径得到命名空间 (`math/ops.ant` -> `math::ops`), 与已有模块重名时加上序号
+    fn namespace_for(&self, path: &Path) -> Arc<str> {
+        let name = path
+            .with_extension("")
+            .components()
+            .filter_map(|it| match it {
+                std::path::Component::Normal(it) => Some(it.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("::");
+
+        let taken = |name: &str| self.loaded.values().any(|it| it.as_deref() == Some(name));
+
+        if !taken(&name) {
+            return name.into();
+        }
+
+        (2..)
+            .map(|i| format!("{name}{i}"))
+            .find(|it| !taken(it))
+            .unwrap()
+            .into()
+    }
+}
+
+/// 取出顶层的 `import` 语句, 其余 token 原样返回
+///
+/// 字符串与注释在词法分析时已经是单独的 token, 其中的 `import` 不会被当作导入
+fn parse_imports(tokens: Vec<Token>) -> Result<(Vec<Token>, Vec<Import>), Diagnostic> {
+    let mut rest: Vec<Token> = Vec::with_capacity(tokens.len());
+    let mut imports = vec![];
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = &tokens[i];
+
+        match token.value.as_ref() {
+            "{" => depth += 1,
+            "}" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+
+        let statement_start = rest
+            .last()
+            .is_none_or(|it| it.line < token.line || matches!(it.value.as_ref(), ";" | "}"));
+
+        if depth > 0 || token.value.as_ref() != "import" || !statement_start {
+            rest.push(token.clone());
+            i += 1;
+            continue;
+        }
+
+        let (import, end) = parse_import(&tokens, i)?;
+        imports.push(import);
+        i = end;
+    }
+
+    Ok((rest, imports))
+}
+
+/// 解析从 `start` 开始的一条 `import`, 返回它与语句之后的位置
+///
+/// `import "path/to/mod.ant"` 或 `import foo.bar` (`foo/bar.ant`), 结尾的 `;` 可以省略
+fn parse_import(tokens: &[Token], start: usize) -> Result<(Import, usize), Diagnostic> {
+    let keyword = &tokens[start];
+    let mut end = start + 1;
+
+    let path = match tokens.get(end) {
+        Some(first) if first.token_type == TokenType::Ident => {
+            let mut parts = vec![first.value.to_string()];
+            end += 1;
+
+            while tokens.get(end).is_some_and(|it| it.value.as_ref() == ".")
+                && let Some(part) = tokens.get(end + 1)
+                && part.token_type == TokenType::Ident
+            {
+                parts.push(part.value.to_string());
+                end += 2;
+            }
+
+            Some(PathBuf::from(parts.join("/")).with_extension("ant"))
+        }
+        // 字符串字面量
+        Some(first)
+            if first.token_type != TokenType::Eof
+                && first.value.chars().any(char::is_alphanumeric) =>
+        {
+            end += 1;
+            Some(PathBuf::from(first.value.trim_matches('"')))
+        }
+        _ => None,
+    };
+
+    let Some(path) = path else {
+        let target = tokens
+            .get(start + 1)
+            .map(|it| it.value.to_string())
+            .unwrap_or_default();
+
+        return Err(
+            Diagnostic::error(tr!("invalid-import", import = target)).with_token(keyword)
+        );
+    };
+
+    let span = Span::from_token(keyword).map(|span| match Span::from_token(&tokens[end - 1]) {
+        Some(last) => Span {
+            end_line: last.end_line,
+            end_column: last.end_column,
+            ..span
+        },
+        None => span,
+    });
+
+    if tokens.get(end).is_some_and(|it| it.value.as_ref() == ";") {
+        end += 1;
+    }
+
+    let alias = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+        .into();
+
+    Ok((Import { path, alias, span }, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell, collections::HashMap, fs, path::Path, rc::Rc, sync::Arc, sync::Mutex,
+    };
+
+    use ant_lexer::Lexer;
+    use ant_token::token_type::TokenType;
+    use ant_type_checker::table::TypeTable;
+
+    use crate::{
+        compiler::{Compiler, jit::create_jit_isa, table::SymbolTable},
+        diagnostic::{SourceMap, codes::ErrorCode},
+        module::{Module, ModuleLoader},
+        session::{CompileOptions, analyze_modules},
+    };
+
+    fn write(dir: &Path, file: &str, content: &str) {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn import_modules_with_namespaces() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write(
+            dir.path(),
+            "main.ant",
+            "import \"util.ant\"\nimport math.ops\n\nfunc add(a: i32, b: i32) -> i32 {\n    a + b + 100i32\n}\n\nfunc main() -> i32 {\n    util.answer() + ops.add(1i32, 2i32)\n}\n",
+        );
+        write(
+            dir.path(),
+            "util.ant",
+            "import math.ops\n\nfunc answer() -> i32 {\n    ops.add(40i32, 0i32)\n}\n",
+        );
+        write(
+            dir.path(),
+            "math/ops.ant",
+            "func add(a: i32, b: i32) -> i32 {\n    a + b\n}\n",
+        );
+
+        let mut sources = SourceMap::new();
+        let modules = ModuleLoader::new(vec![])
+            .load(&[dir.path().join("main.ant")], &mut sources)
+            .unwrap();
+
+        let namespaces = modules
+            .iter()
+            .map(|it| it.namespace.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(namespaces, [Some("math::ops"), Some("util"), None]);
+
+        let type_table = Arc::new(Mutex::new(TypeTable::new().init()));
+        let typed_program = analyze_modules(modules, type_table.clone()).unwrap();
+
+        let options = CompileOptions::new();
+        let program = Compiler::new_jit(
+            create_jit_isa(&options).unwrap(),
+            Rc::new(RefCell::new(SymbolTable::new())),
+            type_table,
+            options,
+        )
+        .unwrap()
+        .compile_jit(typed_program)
+        .unwrap();
+
+        let main: extern "C" fn() -> i32 = program.get("main").unwrap();
+        assert_eq!(main(), 43);
+
+        let add: extern "C" fn(i32, i32) -> i32 = program.get("math::ops::add").unwrap();
+        assert_eq!(add(1, 2), 3);
+    }
+
+    #[test]
+    fn report_cycles_and_missing_modules() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write(dir.path(), "a.ant", "import \"b.ant\"\n");
+        write(dir.path(), "b.ant", "\n\nimport a\n");
+        write(dir.path(), "c.ant", "import \"missing.ant\"\n");
+
+        let err = ModuleLoader::new(vec![])
+            .load(&[dir.path().join("a.ant")], &mut SourceMap::new())
+            .unwrap_err();
+
+        let diagnostic = &err.diagnostics[0];
+        assert_eq!(diagnostic.code, Some(ErrorCode::E0010));
+        assert_eq!(diagnostic.span.as_ref().unwrap().line, 3);
+
+        let err = ModuleLoader::new(vec![])
+            .load(&[dir.path().join("c.ant")], &mut SourceMap::new())
+            .unwrap_err();
+
+        assert_eq!(err.diagnostics[0].code, Some(ErrorCode::E0011));
+    }
+
+    #[test]
+    fn locals_shadow_top_level_definitions() {
+        let source = "func add(a: i32, b: i32) -> i32 {\n    a + b\n}\n\n\
+                      func twice(add: i32) -> i32 {\n    let b = add;\n    add + b\n}\n\n\
+                      func three() -> i32 {\n    let x = \"import add\";\n    add(1i32, 2i32)\n}\n";
+
+        let module = Module {
+            namespace: Some("ops".into()),
+            file: "ops.ant".into(),
+            source: source.to_string(),
+            imports: HashMap::new(),
+        };
+
+        let tokens = Lexer::new(source.to_string(), module.file.clone()).get_tokens();
+
+        let names = module
+            .qualify_tokens(tokens)
+            .unwrap()
+            .iter()
+            .filter(|it| it.token_type == TokenType::Ident)
+            .map(|it| it.value.to_string())
+            .filter(|it| it.ends_with("add") || it.ends_with("twice"))
+            .collect::<Vec<_>>();
+
+        // 参数 `add` 与它的使用都不改名, 顶层函数 `add` 的定义与调用带上命名空间
+        assert_eq!(names, ["ops::add", "ops::twice", "add", "add", "add", "ops::add"]);
+    }
+
+    #[test]
+    fn report_duplicate_aliases() {
+        let dir = tempfile::tempdir().unwrap();
+
+        write(dir.path(), "main.ant", "import a.ops\nimport b.ops\n");
+        write(dir.path(), "a/ops.ant", "func one() -> i32 {\n    1i32\n}\n");
+        write(dir.path(), "b/ops.ant", "func two() -> i32 {\n    2i32\n}\n");
+
+        let err = ModuleLoader::new(vec![])
+            .load(&[dir.path().join("main.ant")], &mut SourceMap::new())
+            .unwrap_err();
+
+        let diagnostic = &err.diagnostics[0];
+        assert_eq!(diagnostic.code, Some(ErrorCode::E0012));
+        assert_eq!(diagnostic.span.as_ref().unwrap().line, 2);
+    }
+}
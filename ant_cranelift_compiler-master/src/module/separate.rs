@@ -0,0 +1,404 @@
+//! 分别编译: 每个模块用自己的 `ObjectModule` 生成目标文件, 并写出接口文件
+//!
+//! 产物缓存在 `deps_dir` 中 (`antc build` 为 `target/<profile>/deps`), 模块的源码、
+//! 依赖的接口与编译设置都没有变化时直接复用; 只修改函数体时接口不变, 依赖方也不需要重新编译
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::Write,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use ant_type_checker::{table::TypeTable, typed_ast::typed_node::TypedNode};
+use cranelift_codegen::isa::TargetIsa;
+
+use crate::{
+    compiler::{Compiler, create_target_isa, isa_fingerprint, table::SymbolTable},
+    diagnostic::{CompileError, Stage},
+    module::{Module, interface::Interface},
+    session::{CompileOptions, analyze_modules},
+    tr,
+};
+
+/// 各模块的目标文件
+#[derive(Debug, Clone)]
+pub struct CompiledModules {
+    /// 依赖在前, 根模块在最后
+    pub objects: Vec<Vec<u8>>,
+    /// 本次重新编译的模块 (根模块记为包名), 其余复用了上次的产物
+    pub rebuilt: Vec<String>,
+}
+
+/// 一起编译的模块: 每个被导入的模块单独一组, 根模块 (入口文件与 `sources`) 合为一组
+struct Unit {
+    /// 根模块为 None
+    namespace: Option<Arc<str>>,
+    modules: Vec<Module>,
+    /// 直接导入的模块
+    deps: Vec<Arc<str>>,
+}
+
+/// 分别编译 `modules` (依赖在前), `name` 为根模块产物的文件名
+///
+/// 脚本模式、被导入的模块又导入了根模块, 或依赖的接口不完整时, 退回到整体编译为一个目标文件
+pub fn compile_modules(
+    modules: Vec<Module>,
+    name: &str,
+    deps_dir: &Path,
+    options: &CompileOptions,
+) -> Result<CompiledModules, CompileError> {
+    let Some(units) = units(&modules).filter(|_| !options.script_mode) else {
+        return compile_whole(modules, name, options);
+    };
+
+    let target_isa =
+        create_target_isa(options).map_err(|it| CompileError::new(Stage::Codegen, it))?;
+
+    // 根模块按 `--crate-type` 编译为库或可执行文件, 切换后不能复用
+    let fingerprint = format!(
+        "{}\n{}\n{:?}\n{:?}\n{}",
+        env!("CARGO_PKG_VERSION"),
+        env!("ANTC_RUSTC_VERSION"),
+        options.opt_level,
+        options.crate_type,
+        isa_fingerprint(&*target_isa)
+    );
+
+    let mut builder = Builder {
+        deps_dir,
+        name,
+        options,
+        target_isa,
+        interfaces: HashMap::new(),
+    };
+
+    let mut compiled = CompiledModules {
+        objects: vec![],
+        rebuilt: vec![],
+    };
+
+    // 每个模块的全部依赖 (包括间接依赖), 依赖在前
+    let mut all_deps: HashMap<Arc<str>, Vec<Arc<str>>> = HashMap::new();
+    let mut order: Vec<Arc<str>> = vec![];
+
+    for unit in units {
+        let deps = order
+            .iter()
+            .filter(|it| {
+                unit.deps
+                    .iter()
+                    .any(|dep| dep == *it || all_deps[dep].contains(*it))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if deps.iter().any(|it| !builder.interfaces[it].complete) {
+            return compile_whole(modules, name, options);
+        }
+
+        // 模块的源码、依赖的接口与编译设置都没变时复用上次的产物
+        let mut key = fingerprint.clone();
+
+        for module in &unit.modules {
+            write!(key, "\n{}\n{}", module.file, module.source).unwrap();
+        }
+
+        for dep in &deps {
+            write!(key, "\n{dep} {}", builder.interfaces[dep].public_hash()).unwrap();
+        }
+
+        let hash = hash(&key);
+
+        let (object_path, interface_path) = builder.artifact_paths(unit.namespace.as_deref());
+
+        let cached = Interface::load(&interface_path)
+            .filter(|it| it.hash == hash)
+            .and_then(|interface| Some((interface, fs::read(&object_path).ok()?)));
+
+        let (interface, object) = match cached {
+            Some(it) => it,
+            None => {
+                compiled
+                    .rebuilt
+                    .push(unit.namespace.as_deref().unwrap_or(name).to_string());
+
+                builder.compile_unit(&unit, &deps, hash)?
+            }
+        };
+
+        compiled.objects.push(object);
+
+        if let Some(namespace) = unit.namespace {
+            all_deps.insert(namespace.clone(), deps);
+            builder.interfaces.insert(namespace.clone(), interface);
+            order.push(namespace);
+        }
+    }
+
+    Ok(compiled)
+}
+
+struct Builder<'a> {
+    deps_dir: &'a Path,
+    name: &'a str,
+    options: &'a CompileOptions,
+    target_isa: Arc<dyn TargetIsa>,
+    /// 已编译模块的接口
+    interfaces: HashMap<Arc<str>, Interface>,
+}
+
+impl Builder<'_> {
+    /// 编译一组模块, 依赖只读取接口; 写出并返回它的接口与目标文件
+    fn compile_unit(
+        &self,
+        unit: &Unit,
+        deps: &[Arc<str>],
+        hash: u64,
+    ) -> Result<(Interface, Vec<u8>), CompileError> {
+        let mut modules = deps
+            .iter()
+            .map(|dep| {
+                let (_, interface_path) = self.artifact_paths(Some(dep));
+                self.interfaces[dep].stub(interface_path.to_string_lossy().to_string().into())
+            })
+            .collect::<Vec<_>>();
+
+        modules.extend(unit.modules.iter().cloned());
+
+        let type_table = Arc::new(Mutex::new(TypeTable::new().init()));
+        let typed_program = analyze_modules(modules, type_table.clone())?;
+
+        // 函数与常量取自单态化之后、优化之前的顶层语句
+        let TypedNode::Program { statements, .. } = &typed_program;
+        let mut interface = Interface::new(
+            unit.namespace.as_deref().unwrap_or_default(),
+            hash,
+            statements,
+        );
+
+        let table = Rc::new(RefCell::new(SymbolTable::new()));
+
+        let program = Compiler::new(
+            self.target_isa.clone(),
+            table.clone(),
+            type_table,
+            self.options.clone(),
+        )
+        .map_err(|it| CompileError::new(Stage::Codegen, it))?
+        .library(unit.namespace.is_some() || self.options.crate_type.is_library())
+        .compile_program(typed_program)
+        .map_err(|it| CompileError::from_diagnostics(Stage::Codegen, it))?;
+
+        // 结构体布局在代码生成时才计算出来
+        interface.add_structs(&table.borrow());
+
+        let (object_path, interface_path) = self.artifact_paths(unit.namespace.as_deref());
+
+        if let Some(parent) = object_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|it| CompileError::new(Stage::Io, it.to_string()))?;
+        }
+
+        fs::write(&object_path, &program.object).map_err(|it| {
+            CompileError::new(
+                Stage::Io,
+                tr!("write-output-failed", path = object_path.display(), error = it),
+            )
+        })?;
+
+        interface
+            .save(&interface_path)
+            .map_err(|it| CompileError::new(Stage::Io, it))?;
+
+        Ok((interface, program.object))
+    }
+
+    /// 模块的产物放在 `deps_dir/modules` 中与命名空间对应的路径 (`math::ops` -> `modules/math/ops.o`),
+    /// 根模块的产物为 `deps_dir/<name>.o`
+    fn artifact_paths(&self, namespace: Option<&str>) -> (PathBuf, PathBuf) {
+        let base = match namespace {
+            Some(namespace) => namespace
+                .split("::")
+                .fold(self.deps_dir.join("modules"), |path, it| path.join(it)),
+            None => self.deps_dir.join(self.name),
+        };
+
+        let with_extension = |extension: &str| {
+            let mut path = base.clone().into_os_string();
+            path.push(extension);
+            PathBuf::from(path)
+        };
+
+        (with_extension(".o"), with_extension(".anti"))
+    }
+}
+
+/// 整体编译为一个目标文件
+fn compile_whole(
+    modules: Vec<Module>,
+    name: &str,
+    options: &CompileOptions,
+) -> Result<CompiledModules, CompileError> {
+    let type_table = Arc::new(Mutex::new(TypeTable::new().init()));
+    let typed_program = analyze_modules(modules, type_table.clone())?;
+
+    let target_isa =
+        create_target_isa(options).map_err(|it| CompileError::new(Stage::Codegen, it))?;
+
+    let program = Compiler::new(
+        target_isa,
+        Rc::new(RefCell::new(SymbolTable::new())),
+        type_table,
+        options.clone(),
+    )
+    .map_err(|it| CompileError::new(Stage::Codegen, it))?
+    .compile_program(typed_program)
+    .map_err(|it| CompileError::from_diagnostics(Stage::Codegen, it))?;
+
+    Ok(CompiledModules {
+        objects: vec![program.object],
+        rebuilt: vec![name.to_string()],
+    })
+}
+
+/// 按模块分组; 被导入的模块又导入了根模块时无法分别编译, 为 None
+fn units(modules: &[Module]) -> Option<Vec<Unit>> {
+    let mut units = vec![];
+    let mut root = Unit {
+        namespace: None,
+        modules: vec![],
+        deps: vec![],
+    };
+
+    for module in modules {
+        let mut deps = module
+            .imports
+            .values()
+            .cloned()
+            .collect::<Option<Vec<_>>>()?;
+
+        match &module.namespace {
+            Some(_) => {
+                deps.sort();
+                deps.dedup();
+
+                units.push(Unit {
+                    namespace: module.namespace.clone(),
+                    modules: vec![module.clone()],
+                    deps,
+                });
+            }
+            None => {
+                root.modules.push(module.clone());
+                root.deps.extend(deps);
+            }
+        }
+    }
+
+    root.deps.sort();
+    root.deps.dedup();
+    units.push(root);
+
+    Some(units)
+}
+
+pub(crate) fn hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use crate::{
+        diagnostic::SourceMap,
+        module::{ModuleLoader, separate::compile_modules},
+        session::{CompileOptions, CrateType},
+    };
+
+    fn write(dir: &Path, file: &str, content: &str) {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn compile_modules_separately_and_reuse_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let deps_dir = dir.path().join("deps");
+
+        write(
+            dir.path(),
+            "main.ant",
+            "import util\n\nfunc main() -> i32 {\n    util.answer()\n}\n",
+        );
+        write(
+            dir.path(),
+            "util.ant",
+            "import math.ops\n\nfunc answer() -> i32 {\n    ops.add(ops.base, 2i32)\n}\n",
+        );
+        write(
+            dir.path(),
+            "math/ops.ant",
+            "const base = 40i32;\n\nfunc add(a: i32, b: i32) -> i32 {\n    a + b\n}\n",
+        );
+
+        let options = CompileOptions::new();
+
+        let build_with = |options: &CompileOptions| {
+            let modules = ModuleLoader::new(vec![])
+                .load(&[dir.path().join("main.ant")], &mut SourceMap::new())
+                .unwrap();
+
+            compile_modules(modules, "app", &deps_dir, options).unwrap()
+        };
+        let build = || build_with(&options);
+
+        let compiled = build();
+        assert_eq!(compiled.objects.len(), 3);
+        assert_eq!(compiled.rebuilt, ["math::ops", "util", "app"]);
+        assert!(deps_dir.join("modules/math/ops.anti").is_file());
+
+        #[cfg(unix)]
+        {
+            let exe = dir.path().join("app");
+            let objects = compiled.objects.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+            crate::compiler::compile_to_executable(&objects, &exe, &options).unwrap();
+
+            let status = std::process::Command::new(&exe).status().unwrap();
+            assert_eq!(status.code(), Some(42));
+        }
+
+        // 没有变化: 全部复用
+        assert!(build().rebuilt.is_empty());
+
+        // 切换产物种类: 所有模块都重新编译, 不复用可执行文件的产物
+        let staticlib = CompileOptions::new().crate_type(CrateType::Staticlib);
+        assert_eq!(build_with(&staticlib).rebuilt, ["math::ops", "util", "app"]);
+        assert_eq!(build().rebuilt, ["math::ops", "util", "app"]);
+
+        // 只修改函数体: 接口不变, 依赖方不需要重新编译
+        write(
+            dir.path(),
+            "math/ops.ant",
+            "const base = 40i32;\n\nfunc add(a: i32, b: i32) -> i32 {\n    b + a\n}\n",
+        );
+        assert_eq!(build().rebuilt, ["math::ops"]);
+
+        // 修改常量: 接口改变, 直接与间接依赖它的模块都重新编译
+        write(
+            dir.path(),
+            "math/ops.ant",
+            "const base = 41i32;\n\nfunc add(a: i32, b: i32) -> i32 {\n    b + a\n}\n",
+        );
+        assert_eq!(build().rebuilt, ["math::ops", "util", "app"]);
+    }
+}
@@ -7,6 +7,7 @@ use ant_type_checker::typed_ast::typed_node::TypedNode;
 use ant_type_checker::typed_ast::typed_stmt::TypedStatement;
 use std::collections::HashMap;
 
+use crate::diagnostic::Diagnostic;
 use crate::traits::NoRepeatPush;
 
 /// 泛型函数信息
@@ -31,14 +32,14 @@ impl Monomorphizer {
     }
 
     /// 执行单态化：收集→替换→生成
-    pub fn monomorphize(&mut self, node: &mut TypedNode) -> Result<(), String> {
+    pub fn monomorphize(&mut self, node: &mut TypedNode) -> Result<(), Diagnostic> {
         self.collect_generic_functions(node)?;
         self.collect_instances(node)?;
         self.generate_and_replace(node)?;
         Ok(())
     }
 
-    fn collect_generic_functions(&mut self, node: &TypedNode) -> Result<(), String> {
+    fn collect_generic_functions(&mut self, node: &TypedNode) -> Result<(), Diagnostic> {
         let TypedNode::Program { statements, .. } = node;
 
         for stmt in statements {
@@ -123,6 +124,10 @@ impl Monomorphizer {
                 Self::collect_in_expr(right, generic_functions);
             }
             
+            TypedExpression::Prefix { right, .. } => {
+                Self::collect_in_expr(right, generic_functions);
+            }
+            
             TypedExpression::If {
                 condition,
                 consequence,
@@ -140,7 +145,7 @@ impl Monomorphizer {
         }
     }
 
-    fn collect_instances(&mut self, node: &TypedNode) -> Result<(), String> {
+    fn collect_instances(&mut self, node: &TypedNode) -> Result<(), Diagnostic> {
         let TypedNode::Program { statements, .. } = node;
         for stmt in statements {
             Self::collect_instances_in_stmt(stmt, &self.generic_functions, &mut self.instances);
@@ -203,6 +208,9 @@ impl Monomorphizer {
                 Self::collect_instances_in_expr(left, generic_functions, instances);
                 Self::collect_instances_in_expr(right, generic_functions, instances);
             }
+            TypedExpression::Prefix { right, .. } => {
+                Self::collect_instances_in_expr(right, generic_functions, instances);
+            }
             TypedExpression::If {
                 condition,
                 consequence,
@@ -219,7 +227,7 @@ impl Monomorphizer {
         }
     }
 
-    fn generate_and_replace(&mut self, node: &mut TypedNode) -> Result<(), String> {
+    fn generate_and_replace(&mut self, node: &mut TypedNode) -> Result<(), Diagnostic> {
         let TypedNode::Program { statements, .. } = node;
 
         // 第一步：生成专门化函数（插入前）
@@ -366,6 +374,10 @@ impl Monomorphizer {
                 Self::replace_calls_in_expr(right, generic_functions);
             }
             
+            TypedExpression::Prefix { right, .. } => {
+                Self::replace_calls_in_expr(right, generic_functions);
+            }
+            
             TypedExpression::If {
                 condition,
                 consequence,
@@ -447,6 +459,10 @@ impl Monomorphizer {
                 Self::substitute_generics_in_expr(right, type_map);
             }
             
+            TypedExpression::Prefix { right, .. } => {
+                Self::substitute_generics_in_expr(right, type_map);
+            }
+            
             TypedExpression::If {
                 condition,
                 consequence,
@@ -0,0 +1,545 @@
+//! AST 级优化 (`-O2` 及以上): 函数内联、常量折叠与无用函数删除
+//!
+//! 所有变换都是保守的: 遇到无法识别的语法结构时保留原样
+
+use std::collections::{HashMap, HashSet};
+
+use ant_ast::expr::IntValue;
+use ant_type_checker::ty::Ty;
+use ant_type_checker::typed_ast::typed_expr::TypedExpression;
+use ant_type_checker::typed_ast::typed_node::TypedNode;
+use ant_type_checker::typed_ast::typed_stmt::TypedStatement;
+
+use crate::compiler::handler::compile_prefix::fold_int_prefix;
+
+/// 可以内联的函数: 函数体只有一个由参数、字面量和运算符组成的表达式
+#[derive(Debug, Clone)]
+struct InlineCandidate {
+    param_names: Vec<String>,
+    body: TypedExpression,
+}
+
+pub struct Optimizer {
+    script_mode: bool,
+    library: bool,
+}
+
+impl Optimizer {
+    pub fn new(script_mode: bool) -> Self {
+        Self {
+            script_mode,
+            library: false,
+        }
+    }
+
+    /// 编译为库时所有顶层函数都是导出的, 不做删除
+    pub fn library(mut self, library: bool) -> Self {
+        self.library = library;
+        self
+    }
+
+    /// 执行优化：内联→折叠→删除无用函数
+    pub fn optimize(&mut self, node: &mut TypedNode) {
+        let TypedNode::Program { statements, .. } = node;
+
+        Self::inline_functions(statements);
+
+        for stmt in statements.iter_mut() {
+            walk_stmt(stmt, &mut Self::fold_expr);
+        }
+
+        self.remove_dead_functions(statements);
+    }
+
+    fn inline_functions(statements: &mut [TypedStatement]) {
+        let candidates = statements
+            .iter()
+            .filter_map(Self::inline_candidate)
+            .collect::<HashMap<_, _>>();
+
+        if candidates.is_empty() {
+            return;
+        }
+
+        for stmt in statements.iter_mut() {
+            walk_stmt(stmt, &mut |expr| {
+                if let Some(inlined) = Self::inline_call(expr, &candidates) {
+                    *expr = inlined;
+                }
+            });
+        }
+    }
+
+    fn inline_candidate(stmt: &TypedStatement) -> Option<(String, InlineCandidate)> {
+        let TypedStatement::ExpressionStatement(TypedExpression::Function {
+            name: Some(name),
+            params,
+            generics_params,
+            block,
+            ty: Ty::Function { ret_type, .. },
+            ..
+        }) = stmt
+        else {
+            return None;
+        };
+
+        // 只内联参数与返回值都是标量的函数, 避免改变 ARC 的引用计数
+        if !generics_params.is_empty() || !is_scalar(ret_type) {
+            return None;
+        }
+
+        let param_names = params
+            .iter()
+            .map(|param| match &**param {
+                TypedExpression::TypeHint(param_name, _, ty) if is_scalar(ty) => {
+                    Some(param_name.value.to_string())
+                }
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let block: &TypedExpression = block;
+        let TypedExpression::Block(_, body, _) = block else {
+            return None;
+        };
+
+        let [TypedStatement::ExpressionStatement(body)] = body.as_slice() else {
+            return None;
+        };
+
+        is_pure_expr(body, &param_names).then(|| {
+            (
+                name.value.to_string(),
+                InlineCandidate {
+                    param_names,
+                    body: body.clone(),
+                },
+            )
+        })
+    }
+
+    /// 实参都是字面量或变量时, 用代入实参后的函数体替换调用
+    fn inline_call(
+        expr: &TypedExpression,
+        candidates: &HashMap<String, InlineCandidate>,
+    ) -> Option<TypedExpression> {
+        let TypedExpression::Call { func, args, .. } = expr else {
+            return None;
+        };
+
+        let TypedExpression::Ident(ident, _) = &**func else {
+            return None;
+        };
+
+        let candidate = candidates.get(ident.value.as_ref())?;
+
+        if args.len() != candidate.param_names.len()
+            || !args.iter().all(|it| {
+                let it: &TypedExpression = it;
+                matches!(
+                    it,
+                    TypedExpression::Int { .. }
+                        | TypedExpression::Bool { .. }
+                        | TypedExpression::Ident(..)
+                )
+            })
+        {
+            return None;
+        }
+
+        let substitutions = candidate
+            .param_names
+            .iter()
+            .zip(args.iter().map(|it| -> &TypedExpression { it }))
+            .collect::<HashMap<_, _>>();
+
+        let mut body = candidate.body.clone();
+
+        walk_expr(&mut body, &mut |expr| {
+            if let TypedExpression::Ident(ident, _) = expr
+                && let Some(arg) = substitutions.get(&ident.value.to_string())
+            {
+                *expr = TypedExpression::clone(arg);
+            }
+        });
+
+        Some(body)
+    }
+
+    fn fold_expr(expr: &mut TypedExpression) {
+        let folded = match expr {
+            TypedExpression::Infix {
+                op, left, right, ..
+            } => match (&**left, &**right) {
+                (
+                    TypedExpression::Int { value: lval, .. },
+                    TypedExpression::Int { value: rval, .. },
+                ) => fold_int(op, *lval, *rval).map(|value| {
+                    // 结果与左操作数类型相同, 复用其 token 与类型
+                    let mut folded = (**left).clone();
+                    if let TypedExpression::Int { value: it, .. } = &mut folded {
+                        *it = value;
+                    }
+                    folded
+                }),
+                _ => None,
+            },
+
+            TypedExpression::Prefix { op, right, .. } => match &**right {
+                TypedExpression::Int { value, .. } => fold_int_prefix(op, *value).map(|value| {
+                    // 结果与操作数类型相同, 复用其 token 与类型
+                    let mut folded = (**right).clone();
+                    if let TypedExpression::Int { value: it, .. } = &mut folded {
+                        *it = value;
+                    }
+                    folded
+                }),
+                TypedExpression::Bool { value, .. } if op.as_ref() == "!" => {
+                    let mut folded = (**right).clone();
+                    if let TypedExpression::Bool { value: it, .. } = &mut folded {
+                        *it = !*value;
+                    }
+                    Some(folded)
+                }
+                _ => None,
+            },
+
+            TypedExpression::If {
+                condition,
+                consequence,
+                else_block,
+                ..
+            } => {
+                let condition: &TypedExpression = condition;
+
+                match (condition, else_block) {
+                    (TypedExpression::Bool { value: true, .. }, _) => {
+                        Some(TypedExpression::clone(consequence))
+                    }
+                    (TypedExpression::Bool { value: false, .. }, Some(it)) => {
+                        Some(TypedExpression::clone(it))
+                    }
+                    _ => None,
+                }
+            }
+
+            _ => None,
+        };
+
+        if let Some(it) = folded {
+            *expr = it;
+        }
+    }
+
+    /// 删除从入口不可达的顶层函数
+    ///
+    /// 入口为 `main` 以及所有顶层函数以外的语句; 非脚本模式下没有 `main` 时
+    /// (如作为库使用) 所有函数都可能被调用, 不做删除
+    fn remove_dead_functions(&self, statements: &mut Vec<TypedStatement>) {
+        let functions = statements
+            .iter()
+            .enumerate()
+            .filter_map(|(i, stmt)| Some((function_name(stmt)?, i)))
+            .collect::<HashMap<_, _>>();
+
+        if self.library || (!self.script_mode && !functions.contains_key("main")) {
+            return;
+        }
+
+        let mut live = HashSet::new();
+        let mut worklist = vec![];
+
+        if let Some(&main) = functions.get("main") {
+            live.insert(main);
+            worklist.push(main);
+        }
+
+        let mut referenced = vec![];
+
+        for stmt in statements.iter_mut() {
+            if function_name(stmt).is_none() && !collect_idents(stmt, &mut referenced) {
+                return;
+            }
+        }
+
+        loop {
+            for name in referenced.drain(..) {
+                if let Some(&i) = functions.get(&name)
+                    && live.insert(i)
+                {
+                    worklist.push(i);
+                }
+            }
+
+            let Some(i) = worklist.pop() else {
+                break;
+            };
+
+            if !collect_idents(&mut statements[i], &mut referenced) {
+                return;
+            }
+        }
+
+        let mut i = 0;
+        statements.retain(|stmt| {
+            let keep = function_name(stmt).is_none() || live.contains(&i);
+            i += 1;
+            keep
+        });
+    }
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+fn function_name(stmt: &TypedStatement) -> Option<String> {
+    match stmt {
+        TypedStatement::ExpressionStatement(TypedExpression::Function {
+            name: Some(name), ..
+        }) => Some(name.value.to_string()),
+        _ => None,
+    }
+}
+
+/// 收集语句中出现的所有标识符, 遇到无法识别的语法结构时返回 false
+fn collect_idents(stmt: &mut TypedStatement, idents: &mut Vec<String>) -> bool {
+    walk_stmt(stmt, &mut |expr| {
+        if let TypedExpression::Ident(ident, _) = expr {
+            idents.push(ident.value.to_string());
+        }
+    })
+}
+
+fn is_scalar(ty: &Ty) -> bool {
+    matches!(ty, Ty::IntTy(_) | Ty::Bool)
+}
+
+/// 只由字面量、参数和运算符组成的表达式
+fn is_pure_expr(expr: &TypedExpression, param_names: &[String]) -> bool {
+    match expr {
+        TypedExpression::Int { .. } | TypedExpression::Bool { .. } => true,
+        TypedExpression::Ident(ident, _) => {
+            param_names.iter().any(|it| it.as_str() == ident.value.as_ref())
+        }
+        TypedExpression::Infix { left, right, .. } => {
+            is_pure_expr(left, param_names) && is_pure_expr(right, param_names)
+        }
+        TypedExpression::Prefix { right, .. } => is_pure_expr(right, param_names),
+        _ => false,
+    }
+}
+
+/// 同类型整数的算术与位运算, 与运行时的结果一致: 按位宽回绕, 移位量按位宽取模
+///
+/// 除数为零与有符号除法溢出在运行时触发 trap, 不折叠
+fn fold_int(op: &str, left: IntValue, right: IntValue) -> Option<IntValue> {
+    macro_rules! fold {
+        ($($variant:ident),*) => {
+            match (left, right) {
+                $(
+                    (IntValue::$variant(l), IntValue::$variant(r)) => IntValue::$variant(match op {
+                        "+" => l.wrapping_add(r),
+                        "-" => l.wrapping_sub(r),
+                        "*" => l.wrapping_mul(r),
+                        "/" => l.checked_div(r)?,
+                        "%" => l.checked_rem(r)?,
+                        "&" => l & r,
+                        "|" => l | r,
+                        "^" => l ^ r,
+                        "<<" => l.wrapping_shl(r as u32),
+                        ">>" => l.wrapping_shr(r as u32),
+                        _ => return None,
+                    }),
+                )*
+                _ => return None,
+            }
+        };
+    }
+
+    Some(fold!(I8, I16, I32, I64, ISize, U8, U16, U32, U64, USize))
+}
+
+/// 后序遍历语句中的所有表达式, 遇到无法识别子节点的语法结构时返回 false
+pub(crate) fn walk_stmt(stmt: &mut TypedStatement, f: &mut impl FnMut(&mut TypedExpression)) -> bool {
+    match stmt {
+        TypedStatement::ExpressionStatement(expr) => walk_expr(expr, f),
+        TypedStatement::Let { value, .. } => walk_expr(value, f),
+        TypedStatement::Const { value, .. } => walk_expr(value, f),
+        TypedStatement::Return { expr, .. } => walk_expr(expr, f),
+        TypedStatement::Block { statements, .. } => {
+            let mut complete = true;
+            for s in statements {
+                complete &= walk_stmt(s, f);
+            }
+            complete
+        }
+        TypedStatement::While {
+            condition, block, ..
+        } => walk_expr(condition, f) & walk_stmt(block, f),
+        TypedStatement::Impl { block, .. } => walk_stmt(block, f),
+        TypedStatement::Struct { .. } | TypedStatement::Extern { .. } => true,
+        _ => false,
+    }
+}
+
+fn walk_expr(expr: &mut TypedExpression, f: &mut impl FnMut(&mut TypedExpression)) -> bool {
+    let complete = match expr {
+        TypedExpression::Int { .. }
+        | TypedExpression::Bool { .. }
+        | TypedExpression::StrLiteral { .. }
+        | TypedExpression::Ident(..)
+        | TypedExpression::TypeHint(..) => true,
+
+        TypedExpression::FieldAccess(obj, _, _) => walk_expr(obj, f),
+
+        TypedExpression::BuildStruct(_, _, fields, _) => {
+            let mut complete = true;
+            for (_, field) in fields {
+                complete &= walk_expr(field, f);
+            }
+            complete
+        }
+
+        TypedExpression::Assign { left, right, .. } => walk_expr(left, f) & walk_expr(right, f),
+
+        TypedExpression::Infix { left, right, .. } => walk_expr(left, f) & walk_expr(right, f),
+
+        TypedExpression::Prefix { right, .. } => walk_expr(right, f),
+
+        TypedExpression::Function { params, block, .. } => {
+            let mut complete = true;
+            for p in params {
+                complete &= walk_expr(p, f);
+            }
+            complete & walk_expr(block, f)
+        }
+
+        TypedExpression::Call { func, args, .. } => {
+            let mut complete = walk_expr(func, f);
+            for arg in args {
+                complete &= walk_expr(arg, f);
+            }
+            complete
+        }
+
+        TypedExpression::If {
+            condition,
+            consequence,
+            else_block,
+            ..
+        } => {
+            let mut complete = walk_expr(condition, f) & walk_expr(consequence, f);
+            if let Some(e) = else_block {
+                complete &= walk_expr(e, f);
+            }
+            complete
+        }
+
+        TypedExpression::Block(_, stmts, _) => {
+            let mut complete = true;
+            for s in stmts {
+                complete &= walk_stmt(s, f);
+            }
+            complete
+        }
+
+        _ => false,
+    };
+
+    f(expr);
+    complete
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use ant_type_checker::{
+        table::TypeTable,
+        typed_ast::{typed_expr::TypedExpression, typed_node::TypedNode, typed_stmt::TypedStatement},
+    };
+
+    use crate::{
+        optimizer::{Optimizer, function_name},
+        session::{CompileOptions, OptLevel, Session, analyze_source},
+    };
+
+    const SOURCE: &str = r#"
+        func square(x: i64) -> i64 {
+            x * x
+        }
+
+        func unused() -> i64 {
+            1i64
+        }
+
+        func main() -> i32 {
+            let a = square(3i64) + 2i64 * 5i64;
+            if a == 19i64 { 0i32 } else { 1i32 }
+        }
+    "#;
+
+    fn optimized(source: &str) -> Vec<TypedStatement> {
+        let type_table = Arc::new(Mutex::new(TypeTable::new().init()));
+        let mut node = analyze_source("<test>".into(), source.into(), type_table).unwrap();
+
+        Optimizer::new(false).optimize(&mut node);
+
+        let TypedNode::Program { statements, .. } = node;
+        statements
+    }
+
+    #[test]
+    fn inline_fold_and_remove_dead_functions() {
+        let statements = optimized(SOURCE);
+
+        let names = statements.iter().filter_map(function_name).collect::<Vec<_>>();
+        assert_eq!(names, ["main"]);
+
+        // square(3i64) + 2i64 * 5i64 => 19i64
+        let Some(TypedStatement::ExpressionStatement(TypedExpression::Function { block, .. })) =
+            statements.first()
+        else {
+            panic!("main not found");
+        };
+
+        let block: &TypedExpression = block;
+        let TypedExpression::Block(_, body, _) = block else {
+            panic!("main has no body");
+        };
+
+        let Some(TypedStatement::Let { value, .. }) = body.first() else {
+            panic!("expected let statement");
+        };
+
+        let value: &TypedExpression = value;
+        assert!(matches!(value, TypedExpression::Int { .. }), "{value}");
+    }
+
+    #[test]
+    fn keep_functions_without_main() {
+        let statements = optimized("func add(a: i64, b: i64) -> i64 { a + b }");
+
+        let names = statements.iter().filter_map(function_name).collect::<Vec<_>>();
+        assert_eq!(names, ["add"]);
+    }
+
+    #[test]
+    fn same_result_at_every_opt_level() {
+        for opt_level in [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3, OptLevel::Size] {
+            let program = Session::new(CompileOptions::new().opt_level(opt_level))
+                .compile(SOURCE)
+                .unwrap();
+
+            let main: extern "C" fn() -> i32 = program.get("main").unwrap();
+            assert_eq!(main(), 0, "{opt_level:?}");
+
+            // `Session` 中的函数都可以从宿主调用, 不会因为内联被删除
+            let square: extern "C" fn(i64) -> i64 = program.get("square").unwrap();
+            assert_eq!(square(7), 49, "{opt_level:?}");
+        }
+    }
+}
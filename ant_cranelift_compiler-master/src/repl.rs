@@ -0,0 +1,184 @@
+//! `antc repl`: 逐条读取语句与表达式, JIT 编译后立即执行并打印结果
+//!
+//! 所有输入共享同一个 `TypeTable`、`SymbolTable` 和 JIT 模块,
+//! 之前定义的 `let`、`struct`、`impl` 与 `func` 对之后的输入都可见
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, c_char},
+    io::{self, BufRead, Write},
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use ant_type_checker::{
+    table::TypeTable,
+    ty::{IntTy, Ty},
+    typed_ast::typed_node::TypedNode,
+};
+use cranelift_jit::JITModule;
+
+use crate::{
+    compiler::{Compiler, jit::create_jit_isa, table::SymbolTable},
+    diagnostic::{CompileError, SourceMap, Stage},
+    session::{CompileOptions, analyze_source},
+    tr,
+};
+
+pub struct Repl {
+    compiler: Compiler<JITModule>,
+    type_table: Arc<Mutex<TypeTable>>,
+    sources: SourceMap,
+    entry_count: usize,
+}
+
+impl Repl {
+    /// 本机不支持 JIT 时返回错误
+    pub fn new() -> Result<Self, CompileError> {
+        let type_table = Arc::new(Mutex::new(TypeTable::new().init()));
+
+        let options = CompileOptions::new().file_name("<repl>");
+
+        let target_isa =
+            create_jit_isa(&options).map_err(|it| CompileError::new(Stage::Codegen, it))?;
+
+        let compiler = Compiler::new_jit(
+            target_isa,
+            Rc::new(RefCell::new(SymbolTable::new())),
+            type_table.clone(),
+            options,
+        )
+        .map_err(|it| CompileError::new(Stage::Codegen, it))?;
+
+        Ok(Self {
+            compiler,
+            type_table,
+            sources: SourceMap::new(),
+            entry_count: 0,
+        })
+    }
+
+    /// 编译并执行一条输入, 返回 `值: 类型`, 结果为 unit 时返回 None
+    pub fn eval(&mut self, input: &str) -> Result<Option<String>, CompileError> {
+        self.entry_count += 1;
+
+        let file: Arc<str> = format!("<repl-{}>", self.entry_count).into();
+        self.sources.add(file.clone(), input.into());
+
+        let typed_program = analyze_source(file, input.to_string(), self.type_table.clone())?;
+
+        let TypedNode::Program { statements, .. } = typed_program;
+
+        let entry = self
+            .compiler
+            .compile_repl_entry(&format!("__repl_{}", self.entry_count), statements)
+            .map_err(|it| CompileError::from_diagnostics(Stage::Codegen, it))?;
+
+        // SAFETY: 入口函数由 compile_repl_entry 生成, 签名固定为 `fn() -> i64`
+        let raw = unsafe {
+            let func: extern "C" fn() -> i64 =
+                std::mem::transmute(self.compiler.get_finalized_function(entry.func_id));
+            func()
+        };
+
+        Ok(format_value(raw, &entry.ty).map(|it| format!("{it}: {}", entry.ty)))
+    }
+
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+}
+
+/// 按类型解释入口函数返回的 i64
+fn format_value(raw: i64, ty: &Ty) -> Option<String> {
+    Some(match ty {
+        Ty::Unit => return None,
+        Ty::Bool => (raw != 0).to_string(),
+        Ty::IntTy(IntTy::U64 | IntTy::USize) => (raw as u64).to_string(),
+        Ty::IntTy(_) => raw.to_string(),
+        Ty::Str if raw == 0 => "null".to_string(),
+        Ty::Str => {
+            // SAFETY: 字符串在数据对象中以 \0 结尾
+            let s = unsafe { CStr::from_ptr(raw as *const c_char) };
+            format!("{:?}", s.to_string_lossy())
+        }
+        _ => format!("<{ty} at {raw:#x}>"),
+    })
+}
+
+/// 括号尚未闭合时继续读取下一行
+fn is_complete(input: &str) -> bool {
+    let mut depth = 0i32;
+
+    for c in input.chars() {
+        match c {
+            '{' | '(' | '[' => depth += 1,
+            '}' | ')' | ']' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    depth <= 0
+}
+
+pub fn run_repl() -> Result<(), CompileError> {
+    let mut repl = Repl::new()?;
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    println!("{}", tr!("repl-welcome"));
+
+    loop {
+        let mut input = String::new();
+
+        loop {
+            print!("{}", if input.is_empty() { ">> " } else { ".. " });
+            let _ = io::stdout().flush();
+
+            let Some(Ok(line)) = lines.next() else {
+                return Ok(());
+            };
+
+            input.push_str(&line);
+            input.push('\n');
+
+            if is_complete(&input) {
+                break;
+            }
+        }
+
+        match input.trim() {
+            "" => continue,
+            ":q" | ":quit" | ":exit" => return Ok(()),
+            _ => {}
+        }
+
+        match repl.eval(&input) {
+            Ok(Some(value)) => println!("{value}"),
+            Ok(None) => {}
+            Err(err) => eprintln!("{}", err.render(repl.sources())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repl::{Repl, is_complete};
+
+    #[test]
+    fn multi_line_input() {
+        assert!(is_complete("let a = 1i64;\n"));
+        assert!(!is_complete("func f() -> i64 {\n"));
+        assert!(is_complete("func f() -> i64 {\n    1i64\n}\n"));
+    }
+
+    #[test]
+    fn definitions_persist_between_entries() {
+        let mut repl = Repl::new().unwrap();
+
+        assert_eq!(repl.eval("let a = 40i64;").unwrap(), None);
+        assert_eq!(repl.eval("func add(x: i64, y: i64) -> i64 { x + y }").unwrap(), None);
+        assert_eq!(repl.eval("add(a, 2i64)").unwrap().as_deref(), Some("42: i64"));
+    }
+}
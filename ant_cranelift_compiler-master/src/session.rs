@@ -0,0 +1,505 @@
+//! 嵌入 API: 在 Rust 程序中编译 Ant 源码, 并调用其中的函数
+//!
+//! ```ignore
+//! extern "C" fn host_log(x: i64) {
+//!     println!("{x}");
+//! }
+//!
+//! let options = CompileOptions::new()
+//!     .host_function("host_log", host_log as extern "C" fn(i64));
+//!
+//! let program = Session::new(options).compile(r#"
+//!     func add(a: i64, b: i64) -> i64 {
+//!         extern "C" func host_log(x: i64);
+//!
+//!         host_log(a);
+//!         a + b
+//!     }
+//! "#)?;
+//!
+//! let add: extern "C" fn(i64, i64) -> i64 = program.get("add")?;
+//! assert_eq!(add(1, 2), 3);
+//! ```
+
+use std::{
+    cell::RefCell,
+    path::PathBuf,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use ant_lexer::Lexer;
+use ant_parser::Parser;
+use ant_token::token_type::TokenType;
+use ant_type_checker::{TypeChecker, table::TypeTable, typed_ast::typed_node::TypedNode};
+use clap::ValueEnum;
+
+use crate::{
+    compiler::{
+        Compiler,
+        jit::{JitFunction, JitProgram, create_jit_isa},
+        table::SymbolTable,
+    },
+    diagnostic::{CompileError, Diagnostic, SourceMap, Stage},
+    module::Module,
+    monomorphizer::Monomorphizer,
+};
+
+/// 优化级别, 对应命令行的 `-O`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptLevel {
+    /// 不优化, 并开启 Cranelift IR 校验
+    #[default]
+    O0,
+    O1,
+    /// 在 O1 的基础上开启 AST 级优化
+    O2,
+    O3,
+    /// `-Os`
+    Size,
+    /// `-Oz`
+    MinSize,
+}
+
+impl OptLevel {
+    /// Cranelift 的 `opt_level` 设置
+    pub fn cranelift_opt_level(self) -> &'static str {
+        match self {
+            OptLevel::O0 => "none",
+            OptLevel::O1 | OptLevel::O2 | OptLevel::O3 => "speed",
+            OptLevel::Size | OptLevel::MinSize => "speed_and_size",
+        }
+    }
+
+    /// 是否开启 IR 校验
+    pub fn enable_verifier(self) -> bool {
+        self == OptLevel::O0
+    }
+
+    /// 是否执行常量折叠、函数内联与无用函数删除
+    pub fn ast_passes(self) -> bool {
+        !matches!(self, OptLevel::O0 | OptLevel::O1)
+    }
+}
+
+/// `--target-cpu`: 生成代码可以使用的 CPU 特性
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TargetCpu {
+    /// 当前 CPU 支持的所有特性 (只能用于本机编译)
+    Native,
+    #[value(name = "x86-64-v2")]
+    X86_64V2,
+    #[value(name = "x86-64-v3")]
+    X86_64V3,
+}
+
+impl TargetCpu {
+    /// 对应的 Cranelift 预设
+    pub fn preset(self) -> Option<&'static str> {
+        match self {
+            TargetCpu::Native => None,
+            TargetCpu::X86_64V2 => Some("x86-64-v2"),
+            TargetCpu::X86_64V3 => Some("x86-64-v3"),
+        }
+    }
+}
+
+/// 链接方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    Static,
+    Dynamic,
+}
+
+/// `--crate-type`: 编译产物的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum CrateType {
+    /// 可执行文件
+    #[default]
+    Bin,
+    /// 静态库 (`lib<name>.a`), 附带 C 头文件
+    Staticlib,
+    /// 动态库 (`lib<name>.so` / `lib<name>.dylib` / `<name>.dll`), 附带 C 头文件
+    Cdylib,
+}
+
+impl CrateType {
+    /// 是否为库: 生成位置无关代码, 不要求 `main`
+    pub fn is_library(self) -> bool {
+        self != CrateType::Bin
+    }
+}
+
+/// 单次编译的选项
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    /// 将顶层语句编译进 `main`
+    pub script_mode: bool,
+    /// 诊断信息中显示的文件名
+    pub file_name: Arc<str>,
+    /// Ant 代码中 `extern "C"` 函数可以解析到的 Rust 函数 (仅 JIT)
+    pub host_functions: Vec<(String, *const u8)>,
+    /// 链接可执行文件时直接交给链接器的文件 (`.o`, `.a`, `.so`)
+    pub link_with: Vec<String>,
+    /// 库搜索路径 (`-L`)
+    pub lib_paths: Vec<String>,
+    /// 按名字链接的库 (`-l`)
+    pub libs: Vec<String>,
+    /// 原样传给链接器的参数
+    pub link_args: Vec<String>,
+    /// 链接器, None 表示使用目标平台的 C 编译器
+    pub linker: Option<String>,
+    /// 链接方式, None 表示使用目标平台的默认方式 (Windows 静态链接, 其余动态链接)
+    pub link_mode: Option<LinkMode>,
+    /// 保留链接过程中的中间文件
+    pub save_temps: bool,
+    /// 优化级别
+    pub opt_level: OptLevel,
+    /// 目标平台, None 表示本机 (JIT 只支持本机)
+    pub target: Option<String>,
+    /// 目标 CPU, None 表示本机编译时使用 native, 交叉编译时使用基础特性
+    pub target_cpu: Option<TargetCpu>,
+    /// 直接传给 Cranelift 的设置 (`-C key=value`)
+    pub codegen_flags: Vec<(String, String)>,
+    /// 编译产物的种类
+    pub crate_type: CrateType,
+    /// 是否生成位置无关可执行文件, None 表示使用目标平台的默认方式 (Windows 以外均为 PIE)
+    pub pie: Option<bool>,
+    /// 增量编译缓存目录, None 表示不使用缓存
+    pub incremental: Option<PathBuf>,
+    /// 并行编译函数的线程数, None 表示使用可用的 CPU 数
+    pub jobs: Option<usize>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            script_mode: false,
+            file_name: "<memory>".into(),
+            host_functions: vec![],
+            link_with: vec![],
+            lib_paths: vec![],
+            libs: vec![],
+            link_args: vec![],
+            linker: None,
+            link_mode: None,
+            save_temps: false,
+            opt_level: OptLevel::O0,
+            target: None,
+            target_cpu: None,
+            codegen_flags: vec![],
+            crate_type: CrateType::Bin,
+            pie: None,
+            incremental: None,
+            jobs: None,
+        }
+    }
+}
+
+impl CompileOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn script_mode(mut self, script_mode: bool) -> Self {
+        self.script_mode = script_mode;
+        self
+    }
+
+    pub fn file_name(mut self, file_name: impl Into<Arc<str>>) -> Self {
+        self.file_name = file_name.into();
+        self
+    }
+
+    pub fn link_with(mut self, files: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.link_with.extend(files.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn lib_paths(mut self, dirs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.lib_paths.extend(dirs.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn libs(mut self, libs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.libs.extend(libs.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn link_args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.link_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn linker(mut self, linker: Option<String>) -> Self {
+        self.linker = linker;
+        self
+    }
+
+    pub fn link_mode(mut self, link_mode: Option<LinkMode>) -> Self {
+        self.link_mode = link_mode;
+        self
+    }
+
+    pub fn save_temps(mut self, save_temps: bool) -> Self {
+        self.save_temps = save_temps;
+        self
+    }
+
+    pub fn opt_level(mut self, opt_level: OptLevel) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    pub fn target(mut self, target: Option<String>) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn target_cpu(mut self, target_cpu: Option<TargetCpu>) -> Self {
+        self.target_cpu = target_cpu;
+        self
+    }
+
+    /// 追加 Cranelift 设置, 如 `("preserve_frame_pointers", "true")`
+    pub fn codegen_flags(
+        mut self,
+        flags: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Self {
+        self.codegen_flags
+            .extend(flags.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    pub fn crate_type(mut self, crate_type: CrateType) -> Self {
+        self.crate_type = crate_type;
+        self
+    }
+
+    pub fn pie(mut self, pie: Option<bool>) -> Self {
+        self.pie = pie;
+        self
+    }
+
+    /// 启用增量编译缓存, 未变化的函数直接使用 `dir` 中缓存的机器码
+    pub fn incremental(mut self, dir: Option<PathBuf>) -> Self {
+        self.incremental = dir;
+        self
+    }
+
+    /// 并行编译函数的线程数, 生成的目标文件与线程数无关
+    pub fn jobs(mut self, jobs: Option<usize>) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// 注册一个宿主函数, Ant 代码通过 `extern "C" func <name>(...)` 调用它
+    pub fn host_function<F: JitFunction>(mut self, name: impl Into<String>, func: F) -> Self {
+        self.host_functions.push((name.into(), func.as_ptr()));
+        self
+    }
+}
+
+/// 一次编译会话: 持有编译选项与源码, 用于渲染诊断信息
+pub struct Session {
+    options: CompileOptions,
+    sources: SourceMap,
+}
+
+impl Session {
+    pub fn new(options: CompileOptions) -> Self {
+        Self {
+            options,
+            sources: SourceMap::new(),
+        }
+    }
+
+    pub fn options(&self) -> &CompileOptions {
+        &self.options
+    }
+
+    /// 用于渲染 [`CompileError`] 的源码表
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// 把源码编译为内存中的 JIT 模块
+    pub fn compile(&mut self, source: &str) -> Result<JitProgram, CompileError> {
+        let file = self.options.file_name.clone();
+        self.sources.add(file.clone(), source.into());
+
+        let target_isa = create_jit_isa(&self.options)
+            .map_err(|it| CompileError::new(Stage::Codegen, it))?;
+
+        let type_table = Arc::new(Mutex::new(TypeTable::new().init()));
+
+        let typed_program = analyze_source(file, source.to_string(), type_table.clone())?;
+
+        Compiler::new_jit(
+            target_isa,
+            Rc::new(RefCell::new(SymbolTable::new())),
+            type_table,
+            self.options.clone(),
+        )
+        .map_err(|it| CompileError::new(Stage::Codegen, it))?
+        // 宿主可以按名字调用任意函数, 不能删除 `main` 用不到的函数
+        .library(true)
+        .compile_jit(typed_program)
+        .map_err(|it| CompileError::from_diagnostics(Stage::Codegen, it))
+    }
+}
+
+/// 词法分析、语法分析、类型检查与单态化
+pub fn analyze_source(
+    file: Arc<str>,
+    source: String,
+    type_table: Arc<Mutex<TypeTable>>,
+) -> Result<TypedNode, CompileError> {
+    analyze_modules(vec![Module::root(file, source)], type_table)
+}
+
+/// 把多个模块作为同一个程序分析, 模块按顺序拼接
+///
+/// 每个 token 保留自己所在的文件, 诊断信息仍指向原文件
+pub fn analyze_modules(
+    modules: Vec<Module>,
+    type_table: Arc<Mutex<TypeTable>>,
+) -> Result<TypedNode, CompileError> {
+    let module_count = modules.len();
+    let mut tokens = vec![];
+    let mut lexer_errors = vec![];
+
+    for (i, module) in modules.into_iter().enumerate() {
+        let mut lexer = Lexer::new(module.source.clone(), module.file.clone());
+
+        let file_tokens = lexer.get_tokens();
+
+        // 继续分析其余模块, 一次报告所有文件中的词法错误
+        if lexer.contains_error() {
+            lexer_errors.extend(
+                lexer
+                    .get_errors()
+                    .iter()
+                    .map(|err| Diagnostic::error(err.message.to_string()).with_token(&err.token)),
+            );
+
+            continue;
+        }
+
+        let mut file_tokens = module
+            .qualify_tokens(file_tokens)
+            .map_err(|it| CompileError::new(Stage::Import, it))?;
+
+        // 只保留最后一个模块的 EOF
+        if i + 1 < module_count {
+            file_tokens.retain(|it| it.token_type != TokenType::Eof);
+        }
+
+        tokens.extend(file_tokens);
+    }
+
+    if !lexer_errors.is_empty() {
+        return Err(CompileError::from_diagnostics(Stage::Lexer, lexer_errors));
+    }
+
+    let program = Parser::new(tokens).parse_program().map_err(|err| {
+        CompileError::new(
+            Stage::Parser,
+            Diagnostic::error(err.message.to_string()).with_token(&err.token),
+        )
+    })?;
+
+    let mut typed_program = TypeChecker::new(type_table)
+        .check_node(program)
+        .map_err(|err| {
+            CompileError::new(
+                Stage::TypeChecker,
+                Diagnostic::error(err.message.to_string()).with_token(&err.token),
+            )
+        })?;
+
+    Monomorphizer::new()
+        .monomorphize(&mut typed_program)
+        .map_err(|it| CompileError::new(Stage::Monomorphizer, it))?;
+
+    Ok(typed_program)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        diagnostic::Stage,
+        session::{CompileOptions, Session},
+    };
+
+    extern "C" fn double(x: i64) -> i64 {
+        x * 2
+    }
+
+    #[test]
+    fn call_ant_function_from_rust() {
+        let options =
+            CompileOptions::new().host_function("double", double as extern "C" fn(i64) -> i64);
+
+        let program = Session::new(options)
+            .compile(
+                r#"
+                func add_doubled(a: i64, b: i64) -> i64 {
+                    extern "C" func double(x: i64) -> i64;
+
+                    double(a) + b
+                }
+                "#,
+            )
+            .unwrap();
+
+        let add_doubled: extern "C" fn(i64, i64) -> i64 = program.get("add_doubled").unwrap();
+        assert_eq!(add_doubled(20, 2), 42);
+
+        assert!(program.get::<extern "C" fn(i32) -> i64>("add_doubled").is_err());
+    }
+
+    #[test]
+    fn sessions_are_independent() {
+        let threads = [false, true].map(|script_mode| {
+            std::thread::spawn(move || {
+                let source = if script_mode {
+                    "let s = \"script\";\n42i32"
+                } else {
+                    "func main() -> i32 {\n    let s = \"aot\";\n    7i32\n}"
+                };
+
+                let program = Session::new(CompileOptions::new().script_mode(script_mode))
+                    .compile(source)
+                    .unwrap();
+
+                let main: extern "C" fn() -> i32 = program.get("main").unwrap();
+                main()
+            })
+        });
+
+        let results = threads.map(|it| it.join().unwrap());
+        assert_eq!(results, [7, 42]);
+    }
+
+    #[test]
+    fn syntax_errors_have_spans() {
+        let cases = [
+            ("func main() -> i32 {\n    let s = \"abc\n}", Stage::Lexer),
+            ("func main() -> i32 {\n    let = 1i32;\n}", Stage::Parser),
+        ];
+
+        for (source, stage) in cases {
+            let err = Session::new(CompileOptions::new().file_name("a.ant"))
+                .compile(source)
+                .err()
+                .unwrap();
+
+            assert_eq!(err.stage, stage);
+
+            let span = err.diagnostics[0].span.as_ref().unwrap();
+            assert_eq!(&*span.file, "a.ant");
+            assert_eq!(span.line, 2);
+        }
+    }
+}